@@ -0,0 +1 @@
+alter table feeds add column poll_interval int;
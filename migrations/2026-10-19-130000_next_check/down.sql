@@ -0,0 +1 @@
+alter table feeds drop column next_check;
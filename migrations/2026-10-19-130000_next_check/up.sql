@@ -0,0 +1 @@
+alter table feeds add column next_check datetime;
@@ -0,0 +1,11 @@
+pragma foreign_keys = on;
+
+create table websub(
+    feed          text     not null primary key references feeds(name) on delete cascade,
+    hub           text     not null,
+    topic         text     not null,
+    callback_id   text,
+    secret        text,
+    lease_expires datetime,
+    renew_at      datetime
+);
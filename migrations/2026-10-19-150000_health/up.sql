@@ -0,0 +1,11 @@
+pragma foreign_keys = on;
+
+create table health(
+    feed             text     not null primary key references feeds(name) on delete cascade,
+    last_attempt     datetime not null,
+    last_success     datetime,
+    last_http_status integer,
+    last_error       text,
+    failures         integer  not null default 0,
+    latest_episode   datetime
+);
@@ -0,0 +1,66 @@
+pragma foreign_keys = on;
+
+create table register_new as
+    select feeds.name as feed, register.guid from register join feeds on feeds.id = register.feed_id;
+create table websub_new as
+    select feeds.name as feed, hub, topic, callback_id, secret, lease_expires, renew_at
+    from websub join feeds on feeds.id = websub.feed_id;
+create table health_new as
+    select feeds.name as feed, last_attempt, last_success, last_http_status, last_error, failures,
+        latest_episode
+    from health join feeds on feeds.id = health.feed_id;
+drop table register;
+drop table websub;
+drop table health;
+
+create table feeds_new as select * from feeds;
+drop table feeds;
+
+create table feeds(
+    name          text     not null primary key,
+    uri           text     not null,
+    backlog       int      not null,
+    fetch_since   datetime,
+    poll_interval int,
+    next_check    datetime
+);
+
+insert into feeds(name, uri, backlog, fetch_since, poll_interval, next_check)
+    select name, uri, backlog, fetch_since, poll_interval, next_check from feeds_new;
+
+create table register(
+    feed text not null references feeds(name) on delete cascade,
+    guid text not null,
+    primary key(feed, guid)
+);
+
+insert into register select * from register_new;
+
+create table websub(
+    feed          text     not null primary key references feeds(name) on delete cascade,
+    hub           text     not null,
+    topic         text     not null,
+    callback_id   text,
+    secret        text,
+    lease_expires datetime,
+    renew_at      datetime
+);
+
+insert into websub select * from websub_new;
+
+create table health(
+    feed             text     not null primary key references feeds(name) on delete cascade,
+    last_attempt     datetime not null,
+    last_success     datetime,
+    last_http_status integer,
+    last_error       text,
+    failures         integer  not null default 0,
+    latest_episode   datetime
+);
+
+insert into health select * from health_new;
+
+drop table register_new;
+drop table websub_new;
+drop table health_new;
+drop table feeds_new;
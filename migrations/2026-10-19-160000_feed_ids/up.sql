@@ -0,0 +1,70 @@
+-- key feeds by number rather than by name, so that they can be renamed. tables are rebuilt by way
+-- of copies, so that nothing is renamed while foreign keys refer to it
+pragma foreign_keys = on;
+
+create table register_old as select * from register;
+create table websub_old as select * from websub;
+create table health_old as select * from health;
+drop table register;
+drop table websub;
+drop table health;
+
+create table feeds_old as select * from feeds;
+drop table feeds;
+
+create table feeds(
+    id            integer  not null primary key,
+    name          text     not null unique,
+    uri           text     not null,
+    backlog       int      not null,
+    fetch_since   datetime,
+    poll_interval int,
+    next_check    datetime
+);
+
+insert into feeds(name, uri, backlog, fetch_since, poll_interval, next_check)
+    select name, uri, backlog, fetch_since, poll_interval, next_check from feeds_old order by name;
+
+create table register(
+    feed_id integer not null references feeds(id) on delete cascade,
+    guid    text    not null,
+    primary key(feed_id, guid)
+);
+
+insert into register(feed_id, guid)
+    select feeds.id, register_old.guid from register_old join feeds on feeds.name = register_old.feed;
+
+create table websub(
+    feed_id       integer  not null primary key references feeds(id) on delete cascade,
+    hub           text     not null,
+    topic         text     not null,
+    callback_id   text,
+    secret        text,
+    lease_expires datetime,
+    renew_at      datetime
+);
+
+insert into websub(feed_id, hub, topic, callback_id, secret, lease_expires, renew_at)
+    select feeds.id, hub, topic, callback_id, secret, lease_expires, renew_at
+    from websub_old join feeds on feeds.name = websub_old.feed;
+
+create table health(
+    feed_id          integer  not null primary key references feeds(id) on delete cascade,
+    last_attempt     datetime not null,
+    last_success     datetime,
+    last_http_status integer,
+    last_error       text,
+    failures         integer  not null default 0,
+    latest_episode   datetime
+);
+
+insert into health(feed_id, last_attempt, last_success, last_http_status, last_error, failures,
+        latest_episode)
+    select feeds.id, last_attempt, last_success, last_http_status, last_error, failures,
+        latest_episode
+    from health_old join feeds on feeds.name = health_old.feed;
+
+drop table register_old;
+drop table websub_old;
+drop table health_old;
+drop table feeds_old;
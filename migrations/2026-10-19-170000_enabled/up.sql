@@ -0,0 +1 @@
+alter table feeds add column enabled boolean not null default 1;
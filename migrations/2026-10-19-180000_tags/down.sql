@@ -0,0 +1,2 @@
+drop table tag_settings;
+drop table tags;
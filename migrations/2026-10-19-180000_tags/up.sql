@@ -0,0 +1,13 @@
+create table tags(
+    feed_id integer not null references feeds(id) on delete cascade,
+    tag     text    not null,
+    primary key (feed_id, tag)
+);
+
+create index tags_by_tag on tags(tag);
+
+create table tag_settings(
+    tag         text not null primary key,
+    downloader  text,
+    date_format text
+);
@@ -0,0 +1,3 @@
+alter table feeds drop column final_uri;
+alter table feeds drop column guid;
+alter table feeds drop column title;
@@ -0,0 +1,3 @@
+alter table feeds add column title text;
+alter table feeds add column guid text;
+alter table feeds add column final_uri text;
@@ -0,0 +1,9 @@
+alter table feeds drop column dedupe;
+
+drop index register_by_enclosure;
+drop index register_by_guid;
+
+alter table register drop column duration;
+alter table register drop column title;
+alter table register drop column enclosure_key;
+alter table register drop column enclosure;
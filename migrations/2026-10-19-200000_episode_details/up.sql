@@ -0,0 +1,9 @@
+alter table register add column enclosure text;
+alter table register add column enclosure_key text;
+alter table register add column title text;
+alter table register add column duration integer;
+
+create index register_by_guid on register(guid);
+create index register_by_enclosure on register(enclosure_key);
+
+alter table feeds add column dedupe boolean;
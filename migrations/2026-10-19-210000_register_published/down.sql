@@ -0,0 +1 @@
+alter table register drop column published;
@@ -0,0 +1 @@
+alter table register drop column length;
@@ -0,0 +1 @@
+alter table register add column length bigint;
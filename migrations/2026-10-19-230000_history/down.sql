@@ -0,0 +1,2 @@
+drop table download_history;
+drop table runs;
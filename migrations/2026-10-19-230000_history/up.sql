@@ -0,0 +1,29 @@
+create table runs(
+    id       integer   not null primary key,
+    started  timestamp not null,
+    finished timestamp,
+    -- the names of the feeds fetched, one per line
+    feeds    text      not null,
+    outcome  text
+);
+
+create index runs_by_start on runs(started);
+
+create table download_history(
+    id          integer   not null primary key,
+    run_id      integer   not null references runs(id) on delete cascade,
+    feed_id     integer   not null references feeds(id) on delete cascade,
+    guid        text      not null,
+    title       text,
+    url         text      not null,
+    command     text      not null,
+    started     timestamp not null,
+    -- in milliseconds
+    duration    integer   not null,
+    exit_status integer,
+    error       text,
+    stderr_tail text
+);
+
+create index download_history_by_run on download_history(run_id);
+create index download_history_by_feed on download_history(feed_id, started);
@@ -0,0 +1 @@
+alter table download_history drop column log_file;
@@ -0,0 +1 @@
+alter table download_history add column log_file text;
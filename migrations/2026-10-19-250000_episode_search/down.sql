@@ -0,0 +1,5 @@
+drop trigger episodes_updated;
+drop trigger episodes_deleted;
+drop trigger episodes_inserted;
+drop table episode_search;
+drop table episodes;
@@ -0,0 +1,36 @@
+create table episodes(
+    id          integer   not null primary key,
+    feed_id     integer   not null references feeds(id) on delete cascade,
+    guid        text      not null,
+    title       text,
+    -- the show notes, as plain text
+    description text,
+    published   timestamp not null,
+    url         text      not null,
+    unique (feed_id, guid)
+);
+
+create virtual table episode_search using fts5(
+    title,
+    description,
+    content = 'episodes',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+
+create trigger episodes_inserted after insert on episodes begin
+    insert into episode_search(rowid, title, description)
+        values (new.id, new.title, new.description);
+end;
+
+create trigger episodes_deleted after delete on episodes begin
+    insert into episode_search(episode_search, rowid, title, description)
+        values ('delete', old.id, old.title, old.description);
+end;
+
+create trigger episodes_updated after update on episodes begin
+    insert into episode_search(episode_search, rowid, title, description)
+        values ('delete', old.id, old.title, old.description);
+    insert into episode_search(rowid, title, description)
+        values (new.id, new.title, new.description);
+end;
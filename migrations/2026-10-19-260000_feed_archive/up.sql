@@ -0,0 +1 @@
+alter table feeds add column archive boolean not null default 0;
@@ -0,0 +1,9 @@
+alter table runs add column feeds text not null default '';
+
+update runs set feeds = coalesce((
+    select group_concat(feeds.name, char(10))
+    from run_feeds join feeds on feeds.id = run_feeds.feed_id
+    where run_feeds.run_id = runs.id
+), '');
+
+drop table run_feeds;
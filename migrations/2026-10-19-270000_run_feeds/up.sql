@@ -0,0 +1,63 @@
+-- remember which feeds each fetch covered by their keys rather than their names, so that the
+-- history still finds a feed's fetches after it's renamed. runs is rebuilt by way of a copy, as
+-- older SQLite can't drop a column, and so is the download history that refers to it
+pragma foreign_keys = on;
+
+create table download_history_old as select * from download_history;
+drop table download_history;
+
+create table runs_old as select * from runs;
+drop table runs;
+
+create table runs(
+    id       integer   not null primary key,
+    started  timestamp not null,
+    finished timestamp,
+    outcome  text
+);
+
+insert into runs(id, started, finished, outcome)
+    select id, started, finished, outcome from runs_old;
+
+create index runs_by_start on runs(started);
+
+create table run_feeds(
+    run_id  integer not null references runs(id) on delete cascade,
+    feed_id integer not null references feeds(id) on delete cascade,
+    primary key(run_id, feed_id)
+);
+
+create index run_feeds_by_feed on run_feeds(feed_id);
+
+insert into run_feeds(run_id, feed_id)
+    select runs_old.id, feeds.id from runs_old join feeds
+    on instr(char(10) || runs_old.feeds || char(10), char(10) || feeds.name || char(10)) > 0;
+
+create table download_history(
+    id          integer   not null primary key,
+    run_id      integer   not null references runs(id) on delete cascade,
+    feed_id     integer   not null references feeds(id) on delete cascade,
+    guid        text      not null,
+    title       text,
+    url         text      not null,
+    command     text      not null,
+    started     timestamp not null,
+    -- in milliseconds
+    duration    integer   not null,
+    exit_status integer,
+    error       text,
+    stderr_tail text,
+    log_file    text
+);
+
+insert into download_history(id, run_id, feed_id, guid, title, url, command, started, duration,
+        exit_status, error, stderr_tail, log_file)
+    select id, run_id, feed_id, guid, title, url, command, started, duration,
+        exit_status, error, stderr_tail, log_file
+    from download_history_old;
+
+create index download_history_by_run on download_history(run_id);
+create index download_history_by_feed on download_history(feed_id, started);
+
+drop table runs_old;
+drop table download_history_old;
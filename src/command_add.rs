@@ -0,0 +1,277 @@
+
+use {
+    crate::{
+        Anyhow, Options,
+        command_fetch::parse_index,
+        directory,
+        lock::{Locks, Wait},
+    },
+    podchamp::{Database, FeedIdentity, GetFeeds, discovery::{self, Alternate}, models::Feed},
+    anyhow::{anyhow, bail, Context as _},
+    chrono::prelude::*,
+    url::Url,
+};
+
+/// What a feed turned out to hold when it was checked
+struct Checked {
+    title:      Option<String>,
+    /// The feed's `podcast:guid`
+    guid:       Option<String>,
+    /// Where the link ended up, after any redirects
+    final_link: Url,
+    episodes:   usize,
+    newest:     Option<DateTime<Utc>>,
+}
+
+/// What was found at a link
+enum Probe {
+    Feed(Checked),
+    /// A web page, with the feeds it links to
+    Page(Vec<Alternate>),
+}
+
+/// Fetches and parses a feed, making sure it has episodes podchamp can download, or if the link is
+/// to a web page, finds the feeds it links to
+async fn probe(link: &Url, now: DateTime<Utc>) -> Anyhow<Probe> {
+    let resp = reqwest::get(link.clone()).await
+        .with_context(|| format!("checking {}", link))?;
+    if !resp.status().is_success() {
+        bail!("{} responded with HTTP status {}", link, resp.status());
+    }
+
+    let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let page = resp.url().clone();
+    let body = resp.bytes().await?;
+    if discovery::is_html(content_type.as_deref(), &body) {
+        return Ok(Probe::Page(discovery::find_alternates(&body, &page)));
+    }
+
+    let raw_index = feed_rs::parser::parse(&body[..])
+        .map_err(|e| anyhow!("{} isn't a feed podchamp can read: {}", link, e))?;
+    let index = parse_index(&raw_index, now);
+    if index.is_empty() {
+        bail!("{} contains no recognizable episodes", link);
+    }
+
+    Ok(Probe::Feed(Checked {
+        title:      raw_index.title.as_ref().map(|title| title.content.trim().to_owned()),
+        guid:       discovery::podcast_guid(&body),
+        final_link: page,
+        episodes:   index.len(),
+        newest:     index.iter().map(|ep| ep.when).max(),
+    }))
+}
+
+/// Finds feeds that look like the same show as the one being added, with the reason why
+fn duplicates<'f>(existing: &'f [Feed<'_>], link: &Url, checked: Option<&Checked>)
+    -> Vec<(&'f str, &'static str)>
+{
+    let comparable = |link: &str| Url::parse(link).ok().map(|link| discovery::comparable(&link));
+    let new_link = discovery::comparable(link);
+    let new_final = checked.map(|checked| discovery::comparable(&checked.final_link));
+
+    existing.iter()
+        .filter_map(|feed| {
+            let old_link = comparable(&feed.uri);
+            let old_final = feed.final_uri.as_deref().and_then(comparable);
+            let same_place = [Some(&new_link), new_final.as_ref()].into_iter().flatten()
+                .any(|new| [old_link.as_ref(), old_final.as_ref()].contains(&Some(new)));
+
+            let guid = checked.and_then(|checked| checked.guid.as_deref());
+            let title = checked.and_then(|checked| checked.title.as_deref());
+            let reason =
+                if old_link.as_ref() == Some(&new_link) {
+                    "the same link"
+                }
+                else if same_place {
+                    "a link that leads to the same place"
+                }
+                else if guid.is_some() && feed.guid.as_deref() == guid {
+                    "the same podcast:guid"
+                }
+                else if title.is_some_and(|title| feed.title.as_deref()
+                    .is_some_and(|old| old.eq_ignore_ascii_case(title)))
+                {
+                    "the same title"
+                }
+                else {
+                    return None;
+                };
+            Some((&feed.name[..], reason))
+        })
+        .collect()
+}
+
+/// Picks one of the feeds a page links to, asking which if there's more than one and somebody's
+/// there to ask
+fn choose(page: &Url, mut alternates: Vec<Alternate>) -> Anyhow<Url> {
+    use std::io::{BufRead as _, IsTerminal as _, Write as _};
+
+    match alternates.len() {
+        0 => bail!("{} is a web page that doesn't link to any feeds", page),
+        1 => return Ok(alternates.remove(0).link),
+        _ => { }
+    }
+
+    eprintln!("{} links to several feeds:", page);
+    for (i, alternate) in alternates.iter().enumerate() {
+        match &alternate.title {
+            Some(title) => eprintln!("{:>3}) {} ({})", i + 1, title, alternate.link),
+            None        => eprintln!("{:>3}) {}", i + 1, alternate.link),
+        }
+    }
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Picking the first; give its link instead of the page's to add another");
+        return Ok(alternates.remove(0).link);
+    }
+
+    loop {
+        eprint!("Which one? [1-{}] ", alternates.len());
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+            bail!("no feed chosen");
+        }
+
+        if let Ok(n) = answer.trim().parse::<usize>() {
+            if (1 ..= alternates.len()).contains(&n) {
+                return Ok(alternates.remove(n - 1).link);
+            }
+        }
+    }
+}
+
+/// Makes a feed name out of a title, as lowercase words joined with hyphens
+fn slug(title: &str) -> String {
+    title.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Looks up a link to a podcast directory to find the feed, or turns a podcast URI scheme like
+/// `itpc:` into a plain web link
+async fn resolve(opts: &Options, link: &Url) -> Anyhow<Url> {
+    match directory::Entry::recognise(link) {
+        None        => Ok(discovery::normalise_scheme(link)?),
+        Some(entry) => {
+            let resolved = entry.resolve(opts).await?;
+            match &resolved.title {
+                Some(title) => eprintln!("Resolved {} ({}) to {}", entry, title, resolved.link),
+                None        => eprintln!("Resolved {} to {}", entry, resolved.link),
+            }
+            Ok(resolved.link)
+        }
+    }
+}
+
+/// Works out the feed a new link for an existing feed stands for, as `add` would
+///
+/// A link that can't be checked is taken as it is, since the feed may just be down for now.
+pub(crate)
+async fn new_link(opts: &Options, link: &Url, now: DateTime<Utc>) -> Anyhow<Url> {
+    let link = resolve(opts, link).await?;
+    match probe(&link, now).await {
+        Ok(Probe::Feed(_)) => Ok(link),
+        Ok(Probe::Page(alternates)) => {
+            let chosen = choose(&link, alternates)?;
+            eprintln!("Found {} on {}", chosen, link);
+            Ok(chosen)
+        }
+        Err(e) => {
+            eprintln!("Couldn't check the new link: {:#}", e);
+            Ok(link)
+        }
+    }
+}
+
+/// Adds a feed, checking first that it's a feed worth adding unless `check` is false
+///
+/// Links to podcast directories are looked up to find the feed, podcast URI schemes like `itpc:`
+/// are turned into plain web links, and when checking, a link to a web page is taken to mean the
+/// feed the page links to. Without a name, the feed is named after its title.
+#[allow(clippy::too_many_arguments)]
+pub(crate)
+async fn add(
+    db:      &mut Database,
+    opts:    &Options,
+    locks:   &Locks,
+    wait:    Wait,
+    name:    Option<&str>,
+    link:    &Url,
+    backlog: std::num::NonZeroU32,
+    check:   bool,
+    force:   bool,
+    now:     DateTime<Utc>,
+) -> Anyhow<()> {
+    let mut link = resolve(opts, link).await?;
+    let checked = match check {
+        false => None,
+        true  => match probe(&link, now).await? {
+            Probe::Feed(checked) => Some(checked),
+            Probe::Page(alternates) => {
+                let chosen = choose(&link, alternates)?;
+                eprintln!("Found {} on {}", chosen, link);
+                link = chosen;
+                match probe(&link, now).await? {
+                    Probe::Feed(checked) => Some(checked),
+                    Probe::Page(_) => bail!("{} is a web page, not a feed", link),
+                }
+            }
+        },
+    };
+
+    if let Some(checked) = &checked {
+        let newest = checked.newest
+            .map(|newest| newest.with_timezone(&Local).format("%F").to_string())
+            .unwrap_or_else(|| "-".into());
+        eprintln!("Found {}: {} episodes, the newest from {}",
+            checked.title.as_deref().unwrap_or("an untitled feed"), checked.episodes, newest);
+    }
+
+    let name = match (name, &checked) {
+        (Some(name), _) => name.to_owned(),
+        (None, Some(Checked{title: Some(title), ..})) if !slug(title).is_empty() => slug(title),
+        (None, Some(_)) => bail!("couldn't make a name out of the feed's title; please give one"),
+        (None, None)    => bail!("a feed added with --no-check needs a name"),
+    };
+
+    let existing = db.get_feeds(GetFeeds::All)?;
+    let duplicates = duplicates(&existing, &link, checked.as_ref());
+    for (feed, reason) in &duplicates {
+        eprintln!("{} looks like the same show as {}, which has {}", name, feed, reason);
+    }
+    if !duplicates.is_empty() && !force {
+        bail!("not adding {}, as it looks like a duplicate; use --force to add it anyway", name);
+    }
+
+    locks.lock_feed(&name, wait).await?;
+    db.add_feed(&name, &link, backlog)?;
+    if let Some(checked) = &checked {
+        db.set_identity(&name, &FeedIdentity {
+            title:      checked.title.as_deref(),
+            guid:       checked.guid.as_deref(),
+            final_link: &checked.final_link,
+        })?;
+    }
+    eprintln!("Added {}", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("The Daily"), "the-daily");
+        assert_eq!(slug("  My Brother, My Brother And Me!  "), "my-brother-my-brother-and-me");
+        assert_eq!(slug("99% Invisible"), "99-invisible");
+        assert_eq!(slug("Café Über"), "café-über");
+        assert_eq!(slug("--- ??? ---"), "");
+    }
+}
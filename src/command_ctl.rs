@@ -0,0 +1,122 @@
+
+use {
+    crate::{
+        Anyhow, Options,
+        control::{ControlClient, FetchRequest, Request, Response},
+        options::ControlCommand,
+        report::{Event, Reporter},
+        table::Table,
+    },
+    anyhow::{anyhow, bail},
+    chrono::prelude::*,
+};
+
+/// Asks the running daemon to fetch some feeds, rather than fetching them here
+pub(crate)
+async fn hand_off_fetch(
+    daemon:   &mut ControlClient,
+    feeds:    &[String],
+    tags:     &[String],
+    request:  FetchRequest,
+    reporter: &Reporter,
+) -> Anyhow<()> {
+    let FetchRequest{force, include_paused, archive} = request;
+
+    // naming a feed means fetching it whether it's due or not, just as when fetching here
+    let named = feeds.iter().map(|feed| Request::Fetch {
+        feed:  Some(feed.clone()),
+        tag:   None,
+        force: true,
+        include_paused,
+        archive,
+    });
+    let tagged = tags.iter().map(|tag| Request::Fetch {
+        feed:  None,
+        tag:   Some(tag.clone()),
+        force,
+        include_paused,
+        archive,
+    });
+
+    let mut requests: Vec<_> = named.chain(tagged).collect();
+    if requests.is_empty() {
+        requests.push(Request::Fetch{feed: None, tag: None, force, include_paused, archive});
+    }
+
+    for request in requests {
+        match daemon.request(&request).await? {
+            Response::Queued => if let Request::Fetch{feed, tag, ..} = &request {
+                reporter.emit(Event::Queued{feed: feed.as_deref(), tag: tag.as_deref()});
+            }
+
+            Response::Error{message} => bail!(message),
+            response => bail!("unexpected response from daemon: {:?}", response),
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes a request of the running daemon, and shows its answer
+pub(crate)
+async fn ctl(opts: &Options, command: &ControlCommand) -> Anyhow<()> {
+    let mut daemon = ControlClient::connect(&opts.runtime_dir_path).await
+        .ok_or_else(|| anyhow!("no podchamp daemon is running"))?;
+
+    let request = match command {
+        ControlCommand::Downloads    => Request::Downloads,
+        ControlCommand::Cancel{id}   => Request::Cancel{id: *id},
+        ControlCommand::Reschedule   => Request::Reschedule,
+        ControlCommand::Status{feed} => Request::Status{feed: feed.clone()},
+    };
+
+    match daemon.request(&request).await? {
+        Response::Downloads{downloads} => {
+            if downloads.is_empty() {
+                eprintln!("No downloads in progress");
+                return Ok(());
+            }
+
+            let mut table = Table::new(["ID", "FEED", "ELAPSED", "EPISODE"]);
+            for download in downloads {
+                let elapsed = download.elapsed_secs;
+                table.push(vec![
+                    download.id.to_string(),
+                    download.feed,
+                    format!("{}m{:02}s", elapsed / 60, elapsed % 60),
+                    download.title.unwrap_or(download.url),
+                ]);
+            }
+            table.print();
+        }
+
+        Response::Status{feeds} => {
+            if feeds.is_empty() {
+                eprintln!("The daemon hasn't checked any feeds yet");
+                return Ok(());
+            }
+
+            let mut table = Table::new(["FEED", "CHECKED", "OUTCOME"]);
+            for status in feeds {
+                let outcome = match &status.error {
+                    Some(error) => format!("failed: {}", error),
+                    None        => format!("{} downloaded, {} failed",
+                        status.downloaded, status.failed),
+                };
+                table.push(vec![
+                    status.feed,
+                    status.checked.with_timezone(&Local).format("%F %T").to_string(),
+                    outcome,
+                ]);
+            }
+            table.print();
+        }
+
+        Response::Cancelled => eprintln!("Cancelled download"),
+        Response::Rescheduling => eprintln!("The daemon will restart its polling schedule"),
+        Response::Queued    => { }
+        Response::Error{message} => bail!(message),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+
+use {
+    crate::{
+        Anyhow, Options,
+        command_fetch::{fetch, FetchScope, ReadArchive},
+        command_search::download_found,
+        control::ControlServer,
+        listener::WebSub,
+        lock::{Locks, Wait},
+        options::Interval,
+        report::{Event, Reporter},
+    },
+    podchamp::{Database, GetFeeds, models::Feed},
+    chrono::prelude::*,
+    std::{collections::{HashMap, HashSet}, time::Duration},
+    tokio::time::Instant,
+};
+
+/// The longest the daemon goes without looking at the database, so that it notices new feeds
+const MAX_NAP: Duration = Duration::from_secs(60);
+
+/// Resolves each time the process is asked to shut down
+struct Shutdown {
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl Shutdown {
+    fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        let terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        Ok(Shutdown {
+            #[cfg(unix)]
+            terminate,
+        })
+    }
+
+    async fn requested(&mut self) {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.terminate.recv()     => { }
+            _ = tokio::signal::ctrl_c()   => { }
+        }
+
+        #[cfg(not(unix))]
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+fn note(reporter: &Reporter, message: String) {
+    reporter.emit(Event::Note{message});
+}
+
+/// Waits for a hub's notice, if there's a listener to hear one
+async fn next_notice(websub: &mut Option<WebSub>) -> crate::listener::Notice {
+    match websub {
+        Some(websub) => websub.next_notice().await,
+        None         => std::future::pending().await,
+    }
+}
+
+/// Waits for some work to finish, unless asked to shut down
+///
+/// On the first shutdown request, any downloads in progress are let finish; on the second, they're
+/// killed. Either way, there's nothing to give once shutdown has been asked for.
+async fn unless_stopped<T>(
+    work:     impl std::future::Future<Output = Anyhow<T>>,
+    shutdown: &mut Shutdown,
+    control:  &ControlServer,
+    reporter: &Reporter,
+) -> Option<Anyhow<T>> {
+    tokio::pin!(work);
+    tokio::select! {
+        result = &mut work => Some(result),
+        _ = shutdown.requested() => {
+            note(reporter, "Shutting down once downloads in progress finish; \
+                signal again to cancel them".into());
+            control.downloads().stop();
+            tokio::select! {
+                result = &mut work => {
+                    if let Err(e) = result {
+                        note(reporter, format!("Error: {}", e));
+                    }
+                }
+                _ = shutdown.requested() => {
+                    note(reporter, "Cancelling downloads in progress".into());
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Checks each feed on its own schedule until asked to stop
+///
+/// On the first shutdown request, the daemon lets any downloads in progress finish; on the second,
+/// it kills them. Given a WebSub listener, it also fetches feeds as soon as their hubs say they've
+/// been updated. Other instances can make requests of the daemon over its control socket, including
+/// to download episodes they've found by searching.
+pub(crate)
+async fn daemon(
+    db:               &mut Database,
+    opts:             &Options,
+    default_interval: Duration,
+    reporter:         &Reporter,
+    locks:            &Locks,
+    mut websub:       Option<WebSub>,
+) -> Anyhow<()> {
+    let mut shutdown = Shutdown::new()?;
+    let control = ControlServer::bind(&opts.runtime_dir_path)?;
+    let mut due = HashMap::<String, Instant>::new();
+    let mut pushed = HashSet::<String>::new();
+
+    let default = Interval(default_interval);
+    note(reporter, format!("Started; checking feeds every {} by default", default));
+
+    loop {
+        let feeds = match db.get_feeds(GetFeeds::All) {
+            Ok(feeds) => feeds,
+            Err(e) => {
+                note(reporter, format!("Error reading feeds: {}", e));
+                Vec::new()
+            }
+        };
+
+        let tags = match db.get_tags() {
+            Ok(tags) => tags,
+            Err(e) => {
+                note(reporter, format!("Error reading tags: {}", e));
+                HashMap::new()
+            }
+        };
+        let tags_of = |feed: &Feed<'_>| tags.get(&feed.id).map_or(&[][..], |tags| &tags[..]);
+
+        let requests = control.take_requests();
+        if requests.reschedule {
+            note(reporter, "Restarting the polling schedule".into());
+            due.clear();
+        }
+
+        // forget about removed feeds, and pick out the ones whose time has come, both by their
+        // polling interval and by what they've said about how often to check them, along with any
+        // that their hubs say have been updated or that have been asked for
+        let now = Instant::now();
+        let utc_now = Utc::now();
+        due.retain(|name, _| feeds.iter().any(|feed| feed.name == *name));
+        let ready: Vec<String> = feeds.iter()
+            .filter(|feed| {
+                let requested = requests.for_feed(&feed.name, tags_of(feed));
+                if !feed.enabled && !requested.is_some_and(|request| request.include_paused) {
+                    return false;
+                }
+
+                let next_check_passed = feed.next_check
+                    .map(|naive| DateTime::<Utc>::from_utc(naive, Utc))
+                    .is_none_or(|next_check| next_check <= utc_now);
+                let interval_passed = due.get(&feed.name[..]).is_none_or(|at| *at <= now);
+
+                pushed.contains(&feed.name[..])
+                    || requested.is_some_and(|request| request.force)
+                    || (requested.is_some() || interval_passed) && next_check_passed
+            })
+            .map(|feed| feed.name.to_string())
+            .collect();
+        pushed.clear();
+
+        // say why any feeds asked for aren't being fetched
+        for feed in &feeds {
+            let requested = requests.for_feed(&feed.name, tags_of(feed)).is_some();
+            if requested && !ready.iter().any(|name| feed.name == *name) {
+                if !feed.enabled {
+                    note(reporter, format!("{} is paused; use --include-paused to fetch it anyway",
+                        feed.name));
+                }
+                else if let Some(next_check) = feed.next_check {
+                    let next_check = DateTime::<Utc>::from_utc(next_check, Utc).with_timezone(&Local);
+                    note(reporter, format!("{} isn't due to be checked until {}; use --force to \
+                        check it anyway", feed.name, next_check.format("%F %T")));
+                }
+            }
+        }
+
+        for feed in feeds.iter().filter(|feed| ready.iter().any(|name| feed.name == *name)) {
+            let interval = feed.poll_interval
+                .map(|secs| Duration::from_secs(secs.max(1) as u64))
+                .unwrap_or(default_interval);
+            due.insert(feed.name.to_string(), now + interval);
+        }
+
+        if !ready.is_empty() {
+            let archive: Vec<String> = feeds.iter()
+                .filter(|feed| ready.iter().any(|name| feed.name == *name))
+                .filter(|feed| requests.for_feed(&feed.name, tags_of(feed))
+                    .is_some_and(|request| request.archive))
+                .map(|feed| feed.name.to_string())
+                .collect();
+
+            // the daemon has already decided these are due
+            let scope = FetchScope {
+                which:          GetFeeds::Named(&ready),
+                force:          true,
+                include_paused: true,
+                wait:           Wait::No,
+                archive:        ReadArchive::Named(&archive),
+            };
+            let round = fetch(db, scope, opts, utc_now, reporter, locks, control.downloads());
+            match unless_stopped(round, &mut shutdown, &control, reporter).await {
+                Some(Ok(summary)) => control.record_round(&ready, &summary, utc_now),
+                Some(Err(e))      => note(reporter, format!("Error: {}", e)),
+                None              => break,
+            }
+        }
+
+        // download whatever searches have turned up
+        let mut stopped = false;
+        for found in &requests.downloads {
+            let download =
+                download_found(db, opts, locks, Wait::No, control.downloads(), reporter, found);
+            match unless_stopped(download, &mut shutdown, &control, reporter).await {
+                Some(Ok(_))  => { }
+                Some(Err(e)) => note(reporter, format!("Error: {}", e)),
+                None         => { stopped = true; break; }
+            }
+        }
+        if stopped {
+            break;
+        }
+
+        // subscribe to any hubs just discovered, and renew subscriptions about to run out
+        if let Some(websub) = &mut websub {
+            if let Err(e) = websub.maintain(db, reporter).await {
+                note(reporter, format!("Error keeping WebSub subscriptions: {}", e));
+            }
+        }
+
+        let wake = due.values().copied()
+            .min()
+            .unwrap_or(now + MAX_NAP)
+            .min(Instant::now() + MAX_NAP);
+
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake) => { }
+            _ = shutdown.requested() => { break; }
+            _ = control.requested() => { }
+            notice = next_notice(&mut websub) => {
+                let websub = websub.as_mut().unwrap();
+                match websub.handle(db, notice, reporter) {
+                    Ok(Some(feed)) => { pushed.insert(feed); }
+                    Ok(None) => { }
+                    Err(e) => note(reporter, format!("Error: {}", e)),
+                }
+            }
+        }
+    }
+
+    note(reporter, "Stopped".into());
+    Ok(())
+}
@@ -1,107 +1,773 @@
 
 use {
-    crate::{Anyhow, Options},
-    podchamp::{Database, GetFeeds, models::Feed, Episode, EpisodeMeta, FeedIndex, plan_fetch},
+    crate::{
+        Anyhow, ExitStatus, Options,
+        options::Command,
+        downloads::Downloads,
+        lock::{FeedLocks, Locks, Wait},
+        hooks::{self, DownloadError, DownloadResult, FeedSettings, FetchSummary},
+        report::{Event, EpisodeInfo, Reporter},
+    },
+    podchamp::{
+        CheckOutcome, Database, GetFeeds, models::{Feed, Registration}, Episode, EpisodeMeta,
+        ArchiveReach, DownloadLog, FeedIdentity, FeedIndex, plan_fetch,
+        discovery,
+        schedule::{self, FeedHints},
+        websub,
+    },
     anyhow::bail,
     chrono::prelude::*,
     futures::{
         stream::FuturesUnordered,
         StreamExt as _,
     },
+    std::cell::RefCell,
     url::Url,
 };
 
+/// The parts of a feed's HTTP response that we care about
+struct FeedResponse {
+    /// Where the feed's link ended up, after any redirects
+    url:           Url,
+    status:        reqwest::StatusCode,
+    cache_control: Option<String>,
+    expires:       Option<String>,
+    link:          Option<String>,
+    body:          bytes::Bytes,
+}
+
+impl FeedResponse {
+    async fn read(resp: reqwest::Response) -> reqwest::Result<Self> {
+        let header = |name| resp.headers().get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+            .map(String::from);
+        let url = resp.url().clone();
+        let status = resp.status();
+        let cache_control = header(reqwest::header::CACHE_CONTROL);
+        let expires = header(reqwest::header::EXPIRES);
+        let link = header(reqwest::header::LINK);
+        let body = resp.bytes().await?;
+        Ok(FeedResponse{url, status, cache_control, expires, link, body})
+    }
+}
+
+/// What came of fetching a feed that could be read
+struct FeedOutcome {
+    downloaded:     u32,
+    latest_episode: Option<DateTime<Utc>>,
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn fetch_feed(
-    join_result: Result<(Feed<'_>, reqwest::Result<bytes::Bytes>), tokio::task::JoinError>,
-    db: &mut Database,
-    now: DateTime<Utc>,
-    opts: &Options,
-) -> Anyhow<u32> {
+    feed:         &Feed<'_>,
+    fetch_result: reqwest::Result<FeedResponse>,
+    db:           &mut Database,
+    now:          DateTime<Utc>,
+    opts:         &Options,
+    reporter:     &Reporter,
+    downloads:    &Downloads,
+    summary:      &mut FetchSummary,
+    run:          i32,
+    archive:      bool,
+) -> Anyhow<FeedOutcome> {
     // handle and parse response
-    let (feed, fetch_result) = join_result?;
-    let bytes = fetch_result?;
-    let raw_index = feed_rs::parser::parse(&bytes[..])?;
+    let response = fetch_result?;
+    reporter.emit(Event::HttpStatus{feed: &feed.name, status: response.status.as_u16()});
+    if !response.status.is_success() {
+        bail!("{} responded with HTTP status {}", &feed.name, response.status);
+    }
+
+    let raw_index = feed_rs::parser::parse(&response.body[..])?;
     let index = parse_index(&raw_index, now);
     if index.is_empty() {
         bail!("{} contains no recognizable episodes", &feed.name);
     }
 
+    // add any older pages it has, if they're wanted
+    let older_pages = match archive {
+        true  => read_archive(feed, &response, &index, now, opts, reporter).await,
+        false => Vec::new(),
+    };
+    let index = FeedIndex::merge(std::iter::once(index)
+        .chain(older_pages.iter().map(|page| parse_index(page, now))));
+
+    // work out when it's next worth looking at
+    let hints = FeedHints::from_feed(&raw_index, &response.body)
+        .with_http_headers(response.cache_control.as_deref(), response.expires.as_deref());
+    let next_check = schedule::next_check(now, &hints, &index);
+    db.set_next_check(&feed.name, &next_check)?;
+    reporter.emit(Event::Scheduled{feed: &feed.name, next_check});
+
+    // remember what it says about itself, to spot the same show being added twice
+    let guid = discovery::podcast_guid(&response.body);
+    db.set_identity(&feed.name, &FeedIdentity {
+        title:      raw_index.title.as_ref().map(|title| title.content.trim()),
+        guid:       guid.as_deref(),
+        final_link: &response.url,
+    })?;
+
+    // keep its episodes' text, for searching
+    db.index_episodes(&feed.name, &index)?;
+
+    // note any hub it advertises, so the daemon can subscribe to push updates
+    if let Ok(fetched_from) = Url::parse(&feed.uri) {
+        if let Some(discovery) = websub::discover(&response.body, response.link.as_deref(), &fetched_from) {
+            db.set_websub_hub(&feed.name, &discovery)?;
+        }
+    }
+
     // fetch logic
-    let plan = plan_fetch(&feed, &index);
+    let plan = plan_fetch(feed, &index);
+    reporter.emit(Event::PlanComputed {
+        feed:        &feed.name,
+        in_index:    index.len(),
+        in_plan:     plan.episodes.len(),
+        fetch_since: plan.set_fetch_since
+            .or_else(|| feed.fetch_since.map(|naive| DateTime::from_utc(naive, Utc))),
+    });
+
     if let Some(threshold) = plan.set_fetch_since {
         db.set_fetch_since(&feed.name, &threshold)?;
     }
 
-    let mut n_fetched = 0;
-    for Episode{meta, id, url, when} in plan.episodes {
+    let overrides = db.get_overrides(&feed.name)?;
+    let settings = FeedSettings::new(opts, &overrides);
+
+    let dedupe = feed.dedupe.unwrap_or(opts.dedupe);
+
+    let mut unregistered = Vec::new();
+    let mut replaced = Vec::new();
+    for episode in plan.episodes {
         // TODO do this in one go for all newest items
-        if !db.is_episode_registered(&feed.name, id)? {
-            start_download(&opts, &feed, meta, url, when).await?;
+        match db.get_registration(&feed.name, episode.id)? {
+            None => unregistered.push(episode),
+
+            // registered before enclosures were recorded; there's nothing to compare with yet
+            Some(registration) if registration.enclosure.is_none() => {
+                db.update_registration(&feed.name, episode.id, &episode.record())?;
+            }
+
+            Some(registration) if is_replaced(&registration, episode) => {
+                reporter.emit(Event::Replaced {
+                    episode: EpisodeInfo::new(&feed.name, episode),
+                    old_url: registration.enclosure.map(String::from),
+                });
+                match opts.redownload_replaced {
+                    true  => replaced.push(episode),
+                    false => db.update_registration(&feed.name, episode.id, &episode.record())?,
+                }
+            }
+
+            Some(_) => { }
+        }
+    }
+    let unregistered =
+        remap_guid_storm(db, feed, &index, plan.episodes.len(), unregistered, reporter)?;
+
+    let mut n_fetched = 0;
+    let mut n_started = 0;
+    let to_fetch = unregistered.into_iter().map(|episode| (episode, false))
+        .chain(replaced.into_iter().map(|episode| (episode, true)));
+    for (episode, replaced) in to_fetch {
+        let info = || EpisodeInfo::new(&feed.name, episode);
+        let record = episode.record();
+
+        // this sees what's been registered so far, by earlier feeds in this run and by other
+        // instances; one fetching another feed at this very moment may still get the same episode
+        if dedupe && !replaced {
+            if let Some(of) = db.find_duplicate(&feed.name, &record)? {
+                db.register_episode(&feed.name, &record)?;
+                reporter.emit(Event::Duplicate{episode: info(), of});
+                continue;
+            }
+        }
+
+        if downloads.stopping() {
+            reporter.emit(Event::Note {
+                message: format!("Stopping; the rest of {}'s episodes will be fetched next time",
+                    feed.name)
+            });
+            break;
+        }
+
+        if opts.max_downloads.is_some_and(|max| n_started >= max) {
+            reporter.emit(Event::Note {
+                message: format!("Stopped after {} downloads from {}; the rest will be fetched \
+                    next time", n_started, feed.name)
+            });
+            break;
+        }
+        n_started += 1;
+
+        if download_episode(db, opts, settings, downloads, run, feed, episode, replaced, reporter,
+            summary).await?
+        {
             n_fetched += 1;
-            db.register_episode(&feed.name, id)?;
         }
     }
 
-    Ok(n_fetched)
+    let latest_episode = index.iter().map(|ep| ep.when).max();
+    Ok(FeedOutcome{downloaded: n_fetched, latest_episode})
+}
+
+/// Downloads an episode, registering it if that works, and records how it went in the history and
+/// the summary before running the episode hook
+///
+/// Gives whether the download worked.
+#[allow(clippy::too_many_arguments)]
+pub(crate)
+async fn download_episode(
+    db:        &mut Database,
+    opts:      &Options,
+    settings:  FeedSettings<'_>,
+    downloads: &Downloads,
+    run:       i32,
+    feed:      &Feed<'_>,
+    episode:   &Episode<'_>,
+    replaced:  bool,
+    reporter:  &Reporter,
+    summary:   &mut FetchSummary,
+) -> Anyhow<bool> {
+    let Episode{meta, url, when, ..} = episode;
+    let info = || EpisodeInfo::new(&feed.name, episode);
+    let record = episode.record();
+
+    reporter.emit(Event::DownloadStarted{episode: info()});
+    let started = Utc::now();
+    let clock = std::time::Instant::now();
+    let log_file = opts.download_log_dir.as_ref()
+        .map(|dir| download_log_path(dir, &feed.name, &started, episode.id));
+    let mut tail = Vec::new();
+    let result = start_download(
+        opts, settings, downloads, feed, meta, url, when, replaced,
+        log_file.as_deref(), &mut tail,
+    ).await;
+
+    let error = result.as_ref().err().map(ToString::to_string);
+    let tail = String::from_utf8_lossy(&tail);
+    let log_file = log_file.as_ref().map(|path| path.to_string_lossy());
+    match &result {
+        Ok(()) => {
+            reporter.emit(Event::DownloadSucceeded{episode: info()});
+            // an episode downloaded again keeps its registration, brought up to date
+            match db.get_registration(&feed.name, episode.id)? {
+                Some(_) => db.update_registration(&feed.name, episode.id, &record)?,
+                None    => db.register_episode(&feed.name, &record)?,
+            }
+        }
+
+        Err(e) => {
+            // output that went to a file hasn't been seen yet, so show how it ended
+            let output = log_file.as_ref().map(|_| {
+                let lines: Vec<&str> = tail.lines().collect();
+                lines[lines.len().saturating_sub(FAILURE_LINES) ..].join("\n")
+            });
+            reporter.emit(Event::DownloadFailed {
+                episode:  info(),
+                error:    e.to_string(),
+                output,
+                log_file: log_file.as_deref(),
+            });
+        }
+    }
+
+    // the download's history is nice to have, but the registration is what matters
+    let logged = db.log_download(run, &feed.name, &DownloadLog {
+        guid:        episode.id,
+        title:       meta.title,
+        url,
+        command:     settings.downloader,
+        started,
+        duration:    clock.elapsed(),
+        exit_status: match &result {
+            Ok(())                            => Some(0),
+            Err(DownloadError::Failed(code))  => *code,
+            Err(_)                            => None,
+        },
+        error:       error.as_deref(),
+        stderr_tail: Some(&tail[..]).filter(|tail| !tail.is_empty()),
+        log_file:    log_file.as_deref(),
+    });
+    if let Err(e) = logged {
+        reporter.emit(Event::Note{message: format!("Error recording download history: {}", e)});
+    }
+
+    summary.record_episode(feed, meta, url, when, &result);
+    let hook = hooks::run_episode_hook(opts, settings, feed, meta, url, when, replaced, &result)
+        .await;
+    if let Err(e) = hook {
+        reporter.emit(Event::HookFailed{feed: Some(&feed.name), error: e.to_string()});
+    }
+
+    Ok(result.is_ok())
+}
+
+/// Reads the older pages of a feed that splits its episodes across several, following their
+/// `next` or `prev-archive` links until they go back as far as the backlog or run out
+///
+/// Reading stops early at `--archive-pages`, at a link back to a page already read, or at a page
+/// that can't be read, going by the pages read so far.
+async fn read_archive(
+    feed:     &Feed<'_>,
+    first:    &FeedResponse,
+    index:    &FeedIndex<'_>,
+    now:      DateTime<Utc>,
+    opts:     &Options,
+    reporter: &Reporter,
+) -> Vec<feed_rs::model::Feed> {
+    let note = |message| reporter.emit(Event::Note{message});
+    let mut pages = Vec::new();
+    let mut reach = ArchiveReach::default();
+    reach.add(index);
+    // the feed's own pages count as read, in case the archive links back to them
+    let mut read: std::collections::HashSet<String> =
+        [Url::parse(&feed.uri).ok(), Some(first.url.clone())].iter()
+            .flatten()
+            .map(discovery::comparable)
+            .collect();
+
+    let mut next = discovery::next_page(&first.body, first.link.as_deref(), &first.url);
+    while let Some(link) = next.take() {
+        if reach.reaches_back(feed) {
+            break;
+        }
+
+        if pages.len() + 1 >= opts.archive_pages {
+            note(format!("Stopped reading {}'s archive after {} pages; --archive-pages allows \
+                more", feed.name, pages.len() + 1));
+            break;
+        }
+
+        if !read.insert(discovery::comparable(&link)) {
+            note(format!("{}'s archive leads back to {}, which was already read; stopping there",
+                feed.name, link));
+            break;
+        }
+
+        let page = async {
+            let response = FeedResponse::read(reqwest::get(link.clone()).await?).await?;
+            if !response.status.is_success() {
+                bail!("HTTP status {}", response.status);
+            }
+            let page = feed_rs::parser::parse(&response.body[..])?;
+            Ok::<_, anyhow::Error>((response, page))
+        };
+        match page.await {
+            Ok((response, page)) => {
+                let header = response.link.as_deref();
+                next = discovery::next_page(&response.body, header, &response.url);
+                reach.add(&parse_index(&page, now));
+                pages.push(page);
+            }
+
+            Err(e) => {
+                note(format!("Couldn't read {} from {}'s archive: {}; going by the pages read so \
+                    far", link, feed.name, e));
+                break;
+            }
+        }
+    }
+
+    if !pages.is_empty() {
+        let pages_read = match pages.len() {
+            1 => "1 page".to_owned(),
+            n => format!("{} pages", n),
+        };
+        note(format!("Read {} of {}'s archive", pages_read, feed.name));
+    }
+    pages
+}
+
+/// How much of the end of a downloader's output to keep in the history
+const OUTPUT_TAIL: usize = 2048;
+/// How many lines of a failed download's logged output to show
+const FAILURE_LINES: usize = 10;
+
+/// Where a file to log a download's output to goes: `<dir>/<feed>/<started>-<guid>.log`
+///
+/// Naming the file after when the download started keeps a retry from overwriting the log of the
+/// attempt before it, and tells apart GUIDs that only differ after the part that fits in the name.
+fn download_log_path(dir: &std::path::Path, feed: &str, started: &DateTime<Utc>, guid: &str)
+    -> std::path::PathBuf
+{
+    // GUIDs are often links, and feed names can be anything
+    let file_name_safe = |s: &str| -> String {
+        let safe: String = s.chars()
+            .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+            .take(100)
+            .collect();
+        safe.trim_start_matches('.').to_owned()
+    };
+
+    dir.join(file_name_safe(feed))
+        .join(format!("{}-{}.log", started.format("%Y%m%dT%H%M%SZ"), file_name_safe(guid)))
+}
+
+/// Where a downloader's output goes
+struct Output<'a> {
+    /// The file it's logged to, if any, with how many more bytes it can take
+    file:      Option<(std::fs::File, u64)>,
+    truncated: bool,
+    /// The end of it, for the history
+    tail:      &'a mut Vec<u8>,
+}
+
+impl Output<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        use std::io::Write as _;
+        match &mut self.file {
+            // without a file, it goes to our standard error
+            None => {
+                let _ = std::io::stderr().write_all(bytes);
+            }
+
+            Some((file, room)) => {
+                let fits = bytes.len().min((*room).try_into().unwrap_or(usize::MAX));
+                let _ = file.write_all(&bytes[.. fits]);
+                *room -= fits as u64;
+                if fits < bytes.len() && !self.truncated {
+                    let _ = file.write_all(b"\n[output truncated]\n");
+                    self.truncated = true;
+                }
+            }
+        }
+
+        self.tail.extend_from_slice(bytes);
+        if self.tail.len() > OUTPUT_TAIL {
+            self.tail.drain(.. self.tail.len() - OUTPUT_TAIL);
+        }
+    }
+}
+
+/// Passes on what a downloader writes to one of its outputs
+async fn relay(stream: Option<impl tokio::io::AsyncRead + Unpin>, output: &RefCell<Output<'_>>) {
+    use tokio::io::AsyncReadExt as _;
+    let mut stream = match stream {
+        Some(stream) => stream,
+        None         => return,
+    };
+
+    let mut buf = [0; 4096];
+    while let Ok(n @ 1 ..) = stream.read(&mut buf).await {
+        output.borrow_mut().write(&buf[.. n]);
+    }
+}
+
+/// Whether an episode's enclosure has been swapped for another since it was registered, going by
+/// its length and its link
+///
+/// Query strings are left out of the comparison, as they often carry signatures or cache-busting
+/// times that change from one fetch to the next without the file changing.
+fn is_replaced(registration: &Registration<'_>, episode: &Episode<'_>) -> bool {
+    let without_query = |key: &str| key.split_once('?').map_or(key, |(path, _)| path).to_owned();
+    let new_key = without_query(&discovery::enclosure_key(episode.url));
+    let moved = registration.enclosure_key.as_deref()
+        .is_some_and(|key| without_query(key) != new_key);
+    let resized = registration.length.zip(episode.meta.length)
+        .is_some_and(|(old, new)| u64::try_from(old).ok() != Some(new));
+    moved || resized
+}
+
+/// How many of the episodes in a plan have to look like already-fetched episodes under new GUIDs
+/// before podchamp believes it
+const STORM_FRACTION: f64 = 0.5;
+/// ... and how many at the least
+const STORM_MINIMUM: usize = 2;
+
+/// Spots a feed having given its old episodes new GUIDs, as happens when a show moves to another
+/// host, and registers them under their new GUIDs rather than let them be downloaded again
+///
+/// Returns the episodes that are still to be fetched.
+fn remap_guid_storm<'e>(
+    db:           &mut Database,
+    feed:         &Feed<'_>,
+    index:        &FeedIndex<'_>,
+    plan_len:     usize,
+    unregistered: Vec<&'e Episode<'e>>,
+    reporter:     &Reporter,
+) -> Anyhow<Vec<&'e Episode<'e>>> {
+    // an old GUID that's still in the feed belongs to an episode that's still there
+    let current: std::collections::HashSet<&str> = index.iter().map(|ep| ep.id).collect();
+    let mut claimed = std::collections::HashSet::new();
+    let mut renamed = Vec::new();
+    for episode in &unregistered {
+        let earlier = db.find_earlier_guids(&feed.name, &episode.record())?;
+        if let Some(old) = earlier.into_iter()
+            .find(|old| !current.contains(&old[..]) && !claimed.contains(old))
+        {
+            claimed.insert(old.clone());
+            renamed.push((*episode, old));
+        }
+    }
+
+    if renamed.len() < STORM_MINIMUM || (renamed.len() as f64) < plan_len as f64 * STORM_FRACTION {
+        return Ok(unregistered);
+    }
+
+    for (episode, old_guid) in &renamed {
+        db.update_registration(&feed.name, old_guid, &episode.record())?;
+        reporter.emit(Event::Remapped {
+            episode:  EpisodeInfo::new(&feed.name, episode),
+            old_guid: old_guid.clone(),
+        });
+    }
+    reporter.emit(Event::Note {
+        message: format!("{} seems to have given its episodes new GUIDs; took {} of them to be \
+            episodes already fetched", feed.name, renamed.len())
+    });
+
+    Ok(unregistered.into_iter()
+        .filter(|episode| !renamed.iter().any(|(renamed, _)| renamed.id == episode.id))
+        .collect())
+}
+
+/// Which feeds to fetch, and how particular to be about it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FetchScope<'a> {
+    pub which: GetFeeds<'a>,
+    /// Fetch feeds even if they aren't due yet
+    pub force: bool,
+    /// Fetch paused feeds too
+    pub include_paused: bool,
+    /// How long to wait for feeds that another instance is fetching
+    pub wait:  Wait,
+    /// Which feeds to read older pages of
+    pub archive: ReadArchive<'a>,
+}
+
+/// Which feeds to read older pages of, for feeds that split their episodes across pages
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ReadArchive<'a> {
+    /// Those set to with `mod ... archive on`
+    AsSet,
+    /// Every feed
+    All,
+    /// The feeds with these names, along with those set to
+    Named(&'a [String]),
 }
 
+impl ReadArchive<'_> {
+    fn includes(&self, feed: &Feed<'_>) -> bool {
+        feed.archive || match self {
+            ReadArchive::AsSet        => false,
+            ReadArchive::All          => true,
+            ReadArchive::Named(names) => names.iter().any(|name| feed.name == *name),
+        }
+    }
+}
+
+/// Fetches new episodes of some feeds
+///
+/// Feeds that another instance is working on are skipped, unless a single feed was asked for, in
+/// which case that's an error. Unless `force` is set or the feed was asked for by name, so are
+/// feeds that aren't due to be checked yet, and unless `include_paused` is set, so are paused
+/// feeds.
 pub(crate)
-async fn fetch<'a, 'db> (
-    db:   &'db mut Database,
-    feed: Option<&'_ str>,
-    opts: &'a Options,
-    now:  DateTime<Utc>,
-) -> Anyhow<()> {
+async fn fetch(
+    db:        &mut Database,
+    scope:     FetchScope<'_>,
+    opts:      &Options,
+    now:       DateTime<Utc>,
+    reporter:  &Reporter,
+    locks:     &Locks,
+    downloads: &Downloads,
+) -> Anyhow<FetchSummary> {
+    let FetchScope{which, force, include_paused, wait, archive} = scope;
+
     // figure out what to fetch
-    let feeds = db.get_feeds(match feed {
-        None       => GetFeeds::All,
-        Some(feed) => GetFeeds::One(feed)
-    })?;
+    let feeds = db.get_feeds(which)?;
+
+    if let GetFeeds::Selected{names, ..} = which {
+        if let Some(name) = names.iter().find(|name| !feeds.iter().any(|feed| feed.name == **name)) {
+            bail!("no feed named {}", name);
+        }
+    }
 
     if feeds.is_empty() {
-        eprintln!("No feeds. You can add one with `podchamp add`.");
-        return Ok(())
+        match which {
+            GetFeeds::Selected{..} => eprintln!("No feeds have those tags."),
+            _ => eprintln!("No feeds. You can add one with `podchamp add`."),
+        }
+        return Ok(FetchSummary::default())
     }
 
-    eprint!("Fetching {}", &feeds[0].name);
-    for feed in &feeds[1..] {
-        eprint!(", {}", &feed.name);
+    let named = |feed: &Feed<'_>| match which {
+        GetFeeds::One(name)                => feed.name == name,
+        GetFeeds::Selected{names, ..}      => names.iter().any(|name| feed.name == *name),
+        GetFeeds::All | GetFeeds::Named(_) => false,
+    };
+
+    let (feeds, paused): (Vec<_>, Vec<_>) = feeds.into_iter()
+        .partition(|feed| feed.enabled || include_paused);
+    for feed in &paused {
+        reporter.emit(Event::Paused{feed: &feed.name});
+        if named(feed) {
+            reporter.emit(Event::Note {
+                message: format!("{} is paused; use --include-paused to fetch it anyway", feed.name)
+            });
+        }
     }
-    eprintln!();
+
+    let is_due = |feed: &Feed<'_>| {
+        force || named(feed) || feed.next_check
+            .map(|naive| DateTime::<Utc>::from_utc(naive, Utc))
+            .is_none_or(|next_check| next_check <= now)
+    };
+
+    let (feeds, not_due): (Vec<_>, Vec<_>) = feeds.into_iter().partition(is_due);
+    for feed in &not_due {
+        let next_check = DateTime::from_utc(feed.next_check.unwrap(), Utc);
+        reporter.emit(Event::NotDue{feed: &feed.name, next_check});
+    }
+
+    if feeds.is_empty() {
+        if !not_due.is_empty() {
+            reporter.emit(Event::Note {
+                message: "No feeds are due to be checked; use --force to check them anyway".into()
+            });
+        }
+        return Ok(FetchSummary::default())
+    }
+
+    reporter.emit(Event::FetchStarted {
+        feeds: feeds.iter().map(|feed| &feed.name[..]).collect()
+    });
+
+    // stake a claim on each feed, leaving alone any that another instance is fetching
+    let mut summary = FetchSummary{n_feeds: feeds.len(), ..Default::default()};
+    // the locks are released however this ends, even if it's with an error
+    let mut locked = FeedLocks::new(locks);
+    for feed in &feeds {
+        match locked.lock(&feed.name, wait).await {
+            Ok(()) => { }
+            Err(e) if matches!(which, GetFeeds::One(_)) => return Err(e),
+            Err(e) => {
+                reporter.emit(Event::FeedFailed{feed: &feed.name, error: e.to_string()});
+                summary.record_feed_failure(&feed.name, &e);
+            }
+        }
+    }
+
+    // another instance may have changed things while we waited
+    let feeds = db.get_feeds(which)?.into_iter()
+        .filter(|feed| locked.contains(&feed.name) && (feed.enabled || include_paused))
+        .filter(|feed| is_due(feed))
+        .collect::<Vec<_>>();
+    let run = db.start_run(now, &feeds.iter().map(|feed| &feed.name[..]).collect::<Vec<_>>())?;
 
     // fetch feed data, supplying responses as they come in
     let web_client = reqwest::Client::new();
     let mut jobs = feeds.into_iter()
         .map(|feed| {
+            reporter.emit(Event::FeedStarted{feed: &feed.name, url: &feed.uri});
             let request = web_client.get(feed.uri.as_ref()).build().unwrap();
             let web_client = web_client.clone();
-            tokio::spawn(async move {
+            // the name is kept out here, so that a job that falls over can still be put down to
+            // its feed
+            let name = feed.name.to_string();
+            let job = tokio::spawn(async move {
                 let resp = match web_client.execute(request).await {
                     Ok(resp) => resp,
-                    Err(e) => return (feed, Anyhow::from(Err(e)))
+                    Err(e) => return (feed, Err(e))
                 };
-                let result = resp.bytes().await;
-                (feed, Anyhow::from(result))
-            })
+                let result = FeedResponse::read(resp).await;
+                (feed, result)
+            });
+            async move { (name, job.await) }
         })
         .collect::<FuturesUnordered<_>>();
 
-    // perform per-feed fetches
-    let mut n_fetched = 0;
-    while let Some(join_result) = jobs.next().await {
-        match fetch_feed(join_result, db, now, opts).await {
-            Ok(n)  => { n_fetched += n; }
-            Err(e) => { eprintln!("Fetch error: {}", e); }
+    // perform per-feed fetches, unless asked to stop
+    while let Some((name, join_result)) = jobs.next().await {
+        if downloads.stopping() {
+            break;
         }
+
+        let (feed, fetch_result) = match join_result {
+            Ok(job) => job,
+            Err(e)  => {
+                let e = anyhow::Error::from(e);
+                reporter.emit(Event::FeedFailed{feed: &name, error: e.to_string()});
+                summary.record_feed_failure(&name, &e);
+                locked.unlock(&name);
+                continue;
+            }
+        };
+
+        let http_status = fetch_result.as_ref().ok().map(|response| response.status.as_u16());
+        let result = fetch_feed(
+            &feed, fetch_result, db, now, opts, reporter, downloads, &mut summary, run,
+            archive.includes(&feed),
+        ).await;
+        let check = match result {
+            Ok(outcome) => {
+                reporter.emit(Event::FeedFinished{feed: &feed.name, downloaded: outcome.downloaded});
+                db.record_check(&feed.name, &CheckOutcome {
+                    at: now,
+                    http_status,
+                    error: None,
+                    latest_episode: outcome.latest_episode,
+                })
+            }
+
+            Err(e) => {
+                let error = e.to_string();
+                let check = db.record_check(&feed.name, &CheckOutcome {
+                    at: now,
+                    http_status,
+                    error: Some(&error),
+                    latest_episode: None,
+                });
+                reporter.emit(match e.downcast_ref::<feed_rs::parser::ParseFeedError>() {
+                    Some(_) => Event::ParseError{feed: &feed.name, error},
+                    None    => Event::FeedFailed{feed: &feed.name, error},
+                });
+                summary.record_feed_failure(&feed.name, &e);
+                check
+            }
+        };
+
+        if let Err(e) = check {
+            reporter.emit(Event::Note{message: format!("Error recording {} health: {}", feed.name, e)});
+        }
+
+        locked.unlock(&feed.name);
     }
 
-    if n_fetched == 0 {
-        eprintln!("Already up-to-date");
+    reporter.emit(Event::Summary{summary: &summary});
+
+    finish_run(db, opts, run, &summary, reporter);
+
+    if let Err(e) = hooks::run_fetch_complete_hook(opts, &summary).await {
+        reporter.emit(Event::HookFailed{feed: None, error: e.to_string()});
     }
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Records how a run went in the history, and forgets runs old enough to be forgotten
+pub(crate)
+fn finish_run(
+    db:       &mut Database,
+    opts:     &Options,
+    run:      i32,
+    summary:  &FetchSummary,
+    reporter: &Reporter,
+) {
+    let outcome = match summary.exit_status() {
+        ExitStatus::Success      => "ok",
+        ExitStatus::TotalFailure => "failed",
+        _                        => "partial",
+    };
+    let keep_since = Utc::now() - chrono::Duration::days(opts.history_days.into());
+    let recorded = db.finish_run(run, Utc::now(), outcome)
+        .and_then(|()| db.prune_history(keep_since, opts.history_runs));
+    if let Err(e) = recorded {
+        reporter.emit(Event::Note{message: format!("Error recording fetch history: {}", e)});
+    }
 }
 
+pub(crate)
 fn parse_index<'a> (index: &'a feed_rs::model::Feed, now: DateTime<Utc>)
     -> FeedIndex<'a>
 {
@@ -112,15 +778,21 @@ fn parse_index<'a> (index: &'a feed_rs::model::Feed, now: DateTime<Utc>)
             // TODO sort this out. as of feed-rs 0.6, rss enclosures are emulated with
             // mediarss media objects, but this is very janky and not really consistent
             // with podcasts as they are normally understood. file a bug? not sure.
-            let url = entry.media.iter()
-                .flat_map(|media_obj| media_obj.content.iter())
-                .find_map(|content| {
-                    let mime = content.content_type.as_ref()?;
-                    if mime.type_() != "audio" { return None; }
-                    content.url.as_ref()
+            let (media_obj, content) = entry.media.iter()
+                .flat_map(|media_obj| {
+                    media_obj.content.iter().map(move |content| (media_obj, content))
+                })
+                .find(|(_, content)| {
+                    content.content_type.as_ref().is_some_and(|mime| mime.type_() == "audio")
+                        && content.url.is_some()
                 })?;
+            let url = content.url.as_ref()?;
             let title = entry.title.as_ref().map(|title| &title.content[..]);
-            let meta = EpisodeMeta{title};
+            let duration = content.duration.or(media_obj.duration);
+            let length = content.size.filter(|&size| size > 0);
+            let description = entry.content.as_ref().and_then(|content| content.body.as_deref())
+                .or_else(|| entry.summary.as_ref().map(|summary| &summary.content[..]));
+            let meta = EpisodeMeta{title, duration, length, description};
             let id = &entry.id;
             Some(Episode{meta, id, url, when})
         })
@@ -129,40 +801,287 @@ fn parse_index<'a> (index: &'a feed_rs::model::Feed, now: DateTime<Utc>)
         .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn start_download(
-    opts: &Options,
-    feed: &Feed<'_>,
-    meta: &EpisodeMeta<'_>,
-    link: &Url,
-    date: &DateTime<Utc>)
-    -> Anyhow<()>
+    opts:        &Options,
+    settings:    FeedSettings<'_>,
+    downloads:   &Downloads,
+    feed:        &Feed<'_>,
+    meta:        &EpisodeMeta<'_>,
+    link:        &Url,
+    date:        &DateTime<Utc>,
+    replaced:    bool,
+    log_file:    Option<&std::path::Path>,
+    // the end of the downloader's output is kept here
+    tail:        &mut Vec<u8>)
+    -> DownloadResult
 {
-    let mut command = tokio::process::Command::new(&opts.downloader);
-    command.arg(link.as_str());
-
-    let date = date.format(&opts.date_format)
-        .to_string();
+    let mut command =
+        hooks::episode_command(settings.downloader, settings, feed, meta, link, date, replaced);
 
-    let envs = [
-        ("PODCHAMP_FEED",        Some(&feed.name[..])),
-        ("PODCHAMP_DATE",        Some(&date[..])),
-        ("PODCHAMP_TITLE",       meta.title),
-    //  ("PODCHAMP_AUTHOR",      item.author()),
-    //  ("PODCHAMP_DESCRIPTION", item.summary),
-    ];
+    // when running as a daemon, give the downloader its own process group, so that if the download
+    // is cancelled, anything it started can be stopped along with it. interactively, it had better
+    // stay in ours, so that it gets the terminal's signals
+    let own_group = cfg!(unix) && matches!(opts.command, Command::Daemon{..});
+    #[cfg(unix)]
+    if own_group {
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    }
 
-    for (var, value) in envs.iter() {
-        if let Some(value) = value {
-            command.env(var, value);
+    // standard error is always captured, for the history, but standard output only if it's logged
+    let file = match log_file {
+        None       => None,
+        Some(path) => {
+            let open = || {
+                std::fs::create_dir_all(path.parent().unwrap_or(path))?;
+                std::fs::File::create(path)
+            };
+            Some((open().map_err(DownloadError::Log)?, opts.download_log_size))
         }
-    }
+    };
 
-    let child = command.spawn()?;
-    let output = child.wait_with_output().await?;
-    if !output.status.success() {
-        bail!("Download command failed with code {:?}", output.status.code());
+    let mut command = tokio::process::Command::from(command);
+    command.kill_on_drop(true);
+    command.stderr(std::process::Stdio::piped());
+    command.stdout(match file {
+        Some(_) => std::process::Stdio::piped(),
+        None    => hooks::child_stdout(opts),
+    });
+    let mut child = command.spawn()?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let output = RefCell::new(Output{file, truncated: false, tail});
+    // the group's ID is the downloader's PID
+    let mut guard = ProcessGroupGuard(child.id().filter(|_| own_group));
+    let ticket = downloads.start(&feed.name, meta.title, link.as_str());
+    let status = tokio::select! {
+        (status, (), ()) = async {
+            tokio::join!(child.wait(), relay(stdout, &output), relay(stderr, &output))
+        } => status?,
+        // dropping the child kills it, and the guard sees to anything it started
+        _ = ticket.cancelled() => return Err(DownloadError::Cancelled),
+    };
+    guard.disarm();
+    if !status.success() {
+        return Err(DownloadError::Failed(status.code()));
     }
 
     Ok(())
 }
 
+/// Terminates a download's process group if the download is abandoned before it finishes
+struct ProcessGroupGuard(Option<u32>);
+
+impl ProcessGroupGuard {
+    /// Leaves the group alone after all, as when the download has finished
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.0.and_then(|pgid| i32::try_from(pgid).ok()) {
+            use nix::{sys::signal::{killpg, Signal}, unistd::Pid};
+            let _ = killpg(Pid::from_raw(pgid), Signal::SIGTERM);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration(link: &str, length: Option<i64>) -> Registration<'static> {
+        let link = Url::parse(link).unwrap();
+        Registration {
+            feed_id:       1,
+            guid:          "ep".into(),
+            enclosure:     Some(link.to_string().into()),
+            enclosure_key: Some(discovery::enclosure_key(&link).into()),
+            title:         None,
+            duration:      None,
+            published:     None,
+            length,
+        }
+    }
+
+    fn replaced(old: &Registration<'_>, link: &str, length: Option<u64>) -> bool {
+        let link = Url::parse(link).unwrap();
+        let episode = Episode {
+            meta: EpisodeMeta{title: None, duration: None, length, description: None},
+            id:   "ep",
+            url:  &link,
+            when: Utc::now(),
+        };
+        is_replaced(old, &episode)
+    }
+
+    #[test]
+    fn download_log_paths() {
+        let started = Utc.ymd(2026, 10, 19).and_hms(9, 30, 5);
+        let path = download_log_path("logs".as_ref(), "../news", &started, "https://x.example/1");
+        assert_eq!(path.to_str(), Some("logs/_news/20261019T093005Z-https___x.example_1.log"));
+
+        let retried = started + chrono::Duration::minutes(5);
+        assert_ne!(download_log_path("logs".as_ref(), "news", &retried, "1"),
+            download_log_path("logs".as_ref(), "news", &started, "1"));
+    }
+
+    #[test]
+    fn replaced_enclosures() {
+        let old = registration("https://cdn.example.com/ep.mp3?updated=1&sig=abc", Some(1000));
+        assert!(!replaced(&old, "https://cdn.example.com/ep.mp3?updated=2&sig=def", Some(1000)));
+        assert!(!replaced(&old, "http://pdst.fm/e/cdn.example.com/ep.mp3", None));
+        assert!(replaced(&old, "https://cdn.example.com/ep-fixed.mp3?updated=2", Some(1000)));
+        assert!(replaced(&old, "https://cdn.example.com/ep.mp3?updated=2", Some(1200)));
+        assert!(!replaced(&registration("https://cdn.example.com/ep.mp3", None),
+            "https://cdn.example.com/ep.mp3", Some(1200)));
+    }
+
+    /// A feed named "show" in a database of its own, removed afterwards
+    struct Show {
+        dir: std::path::PathBuf,
+        db:  Database,
+    }
+
+    impl Show {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("podchamp-remap-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let mut db = Database::open(&dir.join("podchamp.sqlite")).unwrap();
+            let link = Url::parse("https://old.example.com/feed.xml").unwrap();
+            db.add_feed("show", &link, std::num::NonZeroU32::new(1).unwrap()).unwrap();
+            Show{dir, db}
+        }
+
+        fn register(&mut self, episode: &Episode<'_>) {
+            self.db.register_episode("show", &episode.record()).unwrap();
+        }
+
+        fn registered(&self, guid: &str) -> bool {
+            self.db.get_registration("show", guid).unwrap().is_some()
+        }
+
+        /// Runs the check over a plan `plan_len` long, with `index` as the feed's episodes, giving
+        /// the GUIDs of the episodes still to fetch, in order
+        fn remap(&mut self, index: &[Episode<'_>], unregistered: &[&str], plan_len: usize)
+            -> Vec<String>
+        {
+            let feed = self.db.get_feeds(GetFeeds::One("show")).unwrap().pop().unwrap();
+            let index: FeedIndex<'_> = index.iter().cloned().collect();
+            let unregistered = index.iter()
+                .filter(|ep| unregistered.contains(&ep.id))
+                .collect();
+            let reporter = Reporter::new(crate::options::OutputFormat::Human);
+            let mut left: Vec<String> =
+                remap_guid_storm(&mut self.db, &feed, &index, plan_len, unregistered, &reporter)
+                    .unwrap()
+                    .into_iter()
+                    .map(|ep| ep.id.to_owned())
+                    .collect();
+            left.sort();
+            left
+        }
+    }
+
+    impl Drop for Show {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Episodes published on the 1st to the nth, as the old host and the new one give them: the
+    /// same titles and dates, with other GUIDs and enclosures
+    fn hosts(n: u32) -> (Vec<Url>, Vec<Url>) {
+        let links = |host: &str| (1 ..= n)
+            .map(|i| Url::parse(&format!("https://{}/{}.mp3", host, i)).unwrap())
+            .collect();
+        (links("old.example.com"), links("new.example.com"))
+    }
+
+    fn episode<'a>(id: &'a str, title: &'a str, link: &'a Url, day: u32) -> Episode<'a> {
+        Episode {
+            meta: EpisodeMeta{title: Some(title), duration: None, length: None, description: None},
+            id,
+            url:  link,
+            when: Utc.ymd(2026, 10, day).and_hms(12, 0, 0),
+        }
+    }
+
+    const TITLES: [&str; 5] = ["One", "Two", "Three", "Four", "Five"];
+    const OLD: [&str; 5] = ["old1", "old2", "old3", "old4", "old5"];
+    const NEW: [&str; 5] = ["new1", "new2", "new3", "new4", "new5"];
+
+    /// The old host's episodes, registered, and the new host's, with their new GUIDs
+    fn moved<'a>(show: &mut Show, old: &'a [Url], new: &'a [Url]) -> Vec<Episode<'a>> {
+        for (i, link) in old.iter().enumerate() {
+            show.register(&episode(OLD[i], TITLES[i], link, i as u32 + 1));
+        }
+        new.iter().enumerate()
+            .map(|(i, link)| episode(NEW[i], TITLES[i], link, i as u32 + 1))
+            .collect()
+    }
+
+    #[test]
+    fn guid_storm_remapped_over_threshold() {
+        let mut show = Show::new("over");
+        let (old, new) = hosts(3);
+        let index = moved(&mut show, &old, &new);
+
+        assert!(show.remap(&index, &NEW[.. 3], 3).is_empty());
+        for (old, new) in OLD.iter().zip(&NEW).take(3) {
+            assert!(show.registered(new) && !show.registered(old), "{} wasn't remapped", old);
+        }
+    }
+
+    #[test]
+    fn guid_storm_left_alone_under_threshold() {
+        // two of five is under half of the plan
+        let mut show = Show::new("under-fraction");
+        let (old, new) = hosts(5);
+        let mut index = moved(&mut show, &old[.. 2], &new[.. 2]);
+        index.extend((2 .. 5).map(|i| episode(NEW[i], TITLES[i], &new[i], i as u32 + 1)));
+
+        assert_eq!(show.remap(&index, &NEW, 5), NEW);
+        assert!(show.registered("old1") && show.registered("old2"));
+
+        // one of one is the whole plan, but not enough to go on
+        let mut show = Show::new("under-minimum");
+        let (old, new) = hosts(1);
+        let index = moved(&mut show, &old, &new);
+
+        assert_eq!(show.remap(&index, &NEW[.. 1], 1), ["new1"]);
+        assert!(show.registered("old1") && !show.registered("new1"));
+    }
+
+    #[test]
+    fn guid_storm_leaves_guids_still_in_the_feed() {
+        // old1 is still there, alongside a new episode that looks like it
+        let mut show = Show::new("current");
+        let (old, new) = hosts(3);
+        let mut index = moved(&mut show, &old, &new);
+        index.push(episode("old1", "One", &old[0], 1));
+
+        assert_eq!(show.remap(&index, &NEW[.. 3], 3), ["new1"]);
+        assert!(show.registered("old1") && !show.registered("new1"));
+        assert!(show.registered("new2") && show.registered("new3"));
+    }
+
+    #[test]
+    fn guid_storm_remaps_each_old_guid_once() {
+        // new1 and again1 both look like old1, but only one of them can be it
+        let mut show = Show::new("claimed");
+        let (old, new) = hosts(2);
+        let again = Url::parse("https://new.example.com/1-again.mp3").unwrap();
+        let mut index = moved(&mut show, &old, &new);
+        index.push(episode("again1", "One", &again, 1));
+
+        let left = show.remap(&index, &["new1", "new2", "again1"], 3);
+        assert_eq!(left.len(), 1);
+        let remapped = ["new1", "again1"].into_iter().find(|guid| *guid != left[0]).unwrap();
+        assert!(show.registered(remapped) && !show.registered(&left[0]));
+        assert!(!show.registered("old1") && show.registered("new2"));
+    }
+}
@@ -0,0 +1,104 @@
+use {
+    crate::{Anyhow, table::Table},
+    podchamp::{Database, HistoryFilter, models::LoggedDownload},
+    chrono::prelude::*,
+};
+
+fn local(naive: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(naive, Utc)
+        .with_timezone(&Local)
+        .format("%F %R")
+        .to_string()
+}
+
+/// Says roughly how long something took, like `1h 2m`, `3m 4s` or `5.6s`
+fn took(millis: i32) -> String {
+    let secs = millis / 1000;
+    match secs {
+        0 ..= 59    => format!("{:.1}s", millis as f64 / 1000.0),
+        60 ..= 3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _           => format!("{}h {}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+fn outcome(download: &LoggedDownload<'_>) -> String {
+    match (&download.error, download.exit_status) {
+        (None, _)               => "ok".into(),
+        (Some(_), Some(code))   => format!("exit {}", code),
+        (Some(error), None)     => error.to_string(),
+    }
+}
+
+/// Shows the downloads in the fetch history, or with `runs`, the fetches themselves
+pub(crate)
+fn log(db: &Database, filter: HistoryFilter<'_>, runs: bool, long: bool) -> Anyhow<()> {
+    if runs {
+        return list_runs(db, filter);
+    }
+
+    let downloads = db.get_download_history(filter)?;
+    if downloads.is_empty() {
+        eprintln!("No downloads recorded.");
+        return Ok(());
+    }
+
+    if long {
+        for (i, (download, feed)) in downloads.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}  {}  {}",
+                local(download.started), feed, download.title.as_deref().unwrap_or("(untitled)"));
+            println!("    guid:     {}", download.guid);
+            println!("    link:     {}", download.url);
+            println!("    command:  {}", download.command);
+            println!("    result:   {}, after {}", outcome(download), took(download.duration));
+            if let Some(log_file) = &download.log_file {
+                println!("    log:      {}", log_file);
+            }
+            if let Some(tail) = download.stderr_tail.as_deref().filter(|tail| !tail.is_empty()) {
+                println!("    output:");
+                for line in tail.lines() {
+                    println!("        {}", line);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let mut table = Table::new(["WHEN", "FEED", "EPISODE", "RESULT", "TOOK"]);
+    for (download, feed) in &downloads {
+        table.push(vec![
+            local(download.started),
+            feed.clone(),
+            download.title.as_deref().unwrap_or("(untitled)").into(),
+            outcome(download),
+            took(download.duration),
+        ]);
+    }
+
+    table.print();
+    Ok(())
+}
+
+fn list_runs(db: &Database, filter: HistoryFilter<'_>) -> Anyhow<()> {
+    let runs = db.get_runs(filter)?;
+    if runs.is_empty() {
+        eprintln!("No fetches recorded.");
+        return Ok(());
+    }
+
+    let mut table = Table::new(["STARTED", "FINISHED", "OUTCOME", "DOWNLOADS", "FEEDS"]);
+    for (run, feeds, downloads) in &runs {
+        table.push(vec![
+            local(run.started),
+            run.finished.map_or("-".into(), local),
+            run.outcome.as_deref().unwrap_or("unfinished").into(),
+            downloads.to_string(),
+            feeds.join(", "),
+        ]);
+    }
+
+    table.print();
+    Ok(())
+}
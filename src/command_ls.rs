@@ -0,0 +1,223 @@
+
+use {
+    crate::{
+        Anyhow,
+        options::{Column, Interval, ListFormat},
+        table::Table,
+    },
+    podchamp::{Database, models::{Feed, Health}},
+    chrono::prelude::*,
+};
+
+/// Every column, in the order `--long` shows them
+pub(crate) const ALL_COLUMNS: [Column; 11] = [
+    Column::Name,
+    Column::State,
+    Column::Link,
+    Column::Backlog,
+    Column::FetchSince,
+    Column::Interval,
+    Column::NextCheck,
+    Column::Episodes,
+    Column::LastFetched,
+    Column::Tags,
+    Column::Downloader,
+];
+
+/// One value in the list
+///
+/// Missing values sort after everything else, and `ls` keeps them there even in reverse.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Cell {
+    Number(i64),
+    Text(String),
+    Time(DateTime<Utc>),
+    Duration(std::time::Duration),
+    Missing,
+}
+
+impl Cell {
+    fn time(naive: Option<NaiveDateTime>) -> Self {
+        naive.map_or(Cell::Missing, |naive| Cell::Time(DateTime::from_utc(naive, Utc)))
+    }
+
+    fn human(&self) -> String {
+        match self {
+            Cell::Number(n)   => n.to_string(),
+            Cell::Text(text)  => text.clone(),
+            Cell::Time(time)  => time.with_timezone(&Local).format("%F %R").to_string(),
+            Cell::Duration(d) => Interval(*d).to_string(),
+            Cell::Missing     => "-".into(),
+        }
+    }
+
+    fn plain(&self) -> String {
+        match self {
+            Cell::Number(n)   => n.to_string(),
+            Cell::Text(text)  => text.clone(),
+            Cell::Time(time)  => time.to_rfc3339(),
+            Cell::Duration(d) => d.as_secs().to_string(),
+            Cell::Missing     => String::new(),
+        }
+    }
+
+    fn json(&self) -> serde_json::Value {
+        match self {
+            Cell::Number(n)   => (*n).into(),
+            Cell::Text(text)  => text.clone().into(),
+            Cell::Time(time)  => time.to_rfc3339().into(),
+            Cell::Duration(d) => d.as_secs().into(),
+            Cell::Missing     => serde_json::Value::Null,
+        }
+    }
+}
+
+/// The column's name in machine-readable output
+fn key(column: Column) -> &'static str {
+    match column {
+        Column::Name        => "name",
+        Column::State       => "state",
+        Column::Link        => "link",
+        Column::Backlog     => "backlog",
+        Column::FetchSince  => "fetch_since",
+        Column::Interval    => "interval",
+        Column::NextCheck   => "next_check",
+        Column::Episodes    => "episodes",
+        Column::LastFetched => "last_fetched",
+        Column::Tags        => "tags",
+        Column::Downloader  => "downloader",
+    }
+}
+
+/// What's known about a feed besides its settings
+struct Extra<'a> {
+    health:   Option<&'a Health<'a>>,
+    episodes: i64,
+    tags:     &'a [String],
+    /// The downloader a tag of the feed's overrides the usual one with
+    downloader: Option<String>,
+}
+
+fn cell(column: Column, feed: &Feed<'_>, extra: &Extra<'_>) -> Cell {
+    match column {
+        Column::Name        => Cell::Text(feed.name.to_string()),
+        Column::State       => Cell::Text(if feed.enabled { "active" } else { "paused" }.into()),
+        Column::Link        => Cell::Text(feed.uri.to_string()),
+        Column::Backlog     => Cell::Number(feed.backlog.into()),
+        Column::FetchSince  => Cell::time(feed.fetch_since),
+        Column::Interval    => feed.poll_interval.map_or(Cell::Missing, |secs| {
+            Cell::Duration(std::time::Duration::from_secs(secs.max(1) as u64))
+        }),
+        Column::NextCheck   => Cell::time(feed.next_check),
+        Column::Episodes    => Cell::Number(extra.episodes),
+        Column::LastFetched => Cell::time(extra.health.and_then(|health| health.last_success)),
+        Column::Tags        => match extra.tags {
+            []   => Cell::Missing,
+            tags => Cell::Text(tags.join(",")),
+        },
+        Column::Downloader  => extra.downloader.clone().map_or(Cell::Missing, Cell::Text),
+    }
+}
+
+/// Quotes a CSV field, if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else {
+        field.into()
+    }
+}
+
+/// Lists feeds, or with `only_tags`, the feeds with any of those tags
+pub(crate)
+fn ls(db: &Database,
+    columns:   &[Column],
+    only_tags: &[String],
+    sort:      Column,
+    reverse:   bool,
+    format:    ListFormat)
+    -> Anyhow<()>
+{
+    let feeds = db.get_health()?;
+    let episodes = db.count_episodes()?;
+    let tags = db.get_tags()?;
+    let tags_of = |feed: &Feed<'_>| tags.get(&feed.id).map_or(&[][..], |tags| &tags[..]);
+
+    let wants_downloader = sort == Column::Downloader || columns.contains(&Column::Downloader);
+
+    let mut rows: Vec<(Cell, Vec<Cell>)> = feeds.iter()
+        .filter(|(feed, _)| {
+            only_tags.is_empty() || tags_of(feed).iter().any(|tag| only_tags.contains(tag))
+        })
+        .map(|(feed, health)| {
+            let downloader = match wants_downloader {
+                true  => db.get_overrides(&feed.name)?.downloader,
+                false => None,
+            };
+            let extra = Extra {
+                health:   health.as_ref(),
+                episodes: episodes.get(&feed.id).copied().unwrap_or(0),
+                tags:     tags_of(feed),
+                downloader,
+            };
+            let cells = |column| cell(column, feed, &extra);
+            Ok((cells(sort), columns.iter().copied().map(cells).collect()))
+        })
+        .collect::<Anyhow<_>>()?;
+
+    // feeds come sorted by name, and the sort is stable, so ties stay in name order. missing
+    // values stay at the end either way
+    rows.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Cell::Missing, Cell::Missing) => std::cmp::Ordering::Equal,
+        (Cell::Missing, _)             => std::cmp::Ordering::Greater,
+        (_, Cell::Missing)             => std::cmp::Ordering::Less,
+        _ if reverse                   => b.cmp(a),
+        _                              => a.cmp(b),
+    });
+
+    match format {
+        ListFormat::Table => {
+            if rows.is_empty() {
+                match only_tags {
+                    [] => eprintln!("No feeds. You can add one with `podchamp add`."),
+                    _  => eprintln!("No feeds have those tags."),
+                }
+                return Ok(());
+            }
+
+            let headers = columns.iter().map(|&column| key(column).replace('_', " ").to_uppercase());
+            let mut table = Table::new(headers);
+            for (_, cells) in &rows {
+                table.push(cells.iter().map(Cell::human).collect());
+            }
+            table.print();
+        }
+
+        ListFormat::Json => {
+            let feeds: Vec<serde_json::Map<String, serde_json::Value>> = rows.iter()
+                .map(|(_, cells)| columns.iter()
+                    .zip(cells)
+                    .map(|(&column, cell)| (key(column).into(), cell.json()))
+                    .collect())
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&feeds)?);
+        }
+
+        ListFormat::Csv | ListFormat::Tsv => {
+            let (separator, field): (&str, fn(&str) -> String) = match format {
+                ListFormat::Csv => (",", csv_field),
+                _ => ("\t", |field| field.replace(['\t', '\n', '\r'], " ")),
+            };
+
+            let headers: Vec<String> = columns.iter().map(|&column| field(key(column))).collect();
+            println!("{}", headers.join(separator));
+            for (_, cells) in &rows {
+                let fields: Vec<String> = cells.iter().map(|cell| field(&cell.plain())).collect();
+                println!("{}", fields.join(separator));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+
+use {
+    crate::{
+        Anyhow, ExitStatus, Options,
+        command_fetch::{download_episode, finish_run},
+        control::{ControlClient, FoundEpisode, Request, Response},
+        downloads::Downloads,
+        hooks::{FeedSettings, FetchSummary},
+        lock::{FeedLocks, Locks, Wait},
+        options::OutputFormat,
+        report::{Event, Reporter},
+        table::Table,
+    },
+    podchamp::{Database, Episode, EpisodeMeta, GetFeeds},
+    anyhow::{bail, Context as _},
+    chrono::prelude::*,
+    url::Url,
+};
+
+/// What to search, and what to do with what's found
+pub(crate) struct SearchScope<'a> {
+    pub feeds:    &'a [String],
+    pub tags:     &'a [String],
+    pub limit:    u32,
+    /// Show the matching part of each episode's show notes
+    pub long:     bool,
+    /// Which result to download, counting from 1
+    pub download: Option<usize>,
+}
+
+fn local_date(naive: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(naive, Utc)
+        .with_timezone(&Local)
+        .format("%F")
+        .to_string()
+}
+
+/// Lists the episodes that match a query, and downloads one if asked to
+///
+/// As with fetching, a running daemon is asked to do the downloading.
+pub(crate)
+async fn search(
+    db:     &mut Database,
+    opts:   &Options,
+    locks:  &Locks,
+    wait:   Wait,
+    query:  &str,
+    scope:  SearchScope<'_>,
+    daemon: Option<ControlClient>,
+) -> Anyhow<ExitStatus> {
+    let SearchScope{feeds, tags, limit, long, download} = scope;
+
+    let feed_ids = match (feeds, tags) {
+        ([], []) => None,
+        (names, tags) => {
+            let found = db.get_feeds(GetFeeds::Selected{names, tags})?;
+            let missing = names.iter().find(|name| !found.iter().any(|feed| feed.name == **name));
+            if let Some(name) = missing {
+                bail!("no feed named {}", name);
+            }
+            Some(found.iter().map(|feed| feed.id).collect::<Vec<_>>())
+        }
+    };
+
+    let hits = db.search(query, feed_ids.as_deref(), limit)?;
+    if hits.is_empty() {
+        eprintln!("No episodes found. Feeds are indexed as they're fetched.");
+        return Ok(ExitStatus::Success);
+    }
+
+    if long {
+        for (i, hit) in hits.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{:>3}) {}  {}  {}", i + 1, local_date(hit.published), hit.feed,
+                hit.title.as_deref().unwrap_or("(untitled)"));
+            if let Some(snippet) = hit.snippet.as_deref().filter(|snippet| !snippet.is_empty()) {
+                println!("     {}", snippet);
+            }
+        }
+    }
+    else {
+        let mut table = Table::new(["#", "FEED", "DATE", "TITLE"]);
+        for (i, hit) in hits.iter().enumerate() {
+            table.push(vec![
+                (i + 1).to_string(),
+                hit.feed.clone(),
+                local_date(hit.published),
+                hit.title.as_deref().unwrap_or("(untitled)").into(),
+            ]);
+        }
+        table.print();
+    }
+
+    let hit = match download {
+        None    => return Ok(ExitStatus::Success),
+        Some(n) => match hits.get(n - 1) {
+            Some(hit) => hit,
+            None      => bail!("there's no result {} to download", n),
+        },
+    };
+    let found = FoundEpisode {
+        feed:      hit.feed.clone(),
+        guid:      hit.guid.clone(),
+        url:       hit.url.clone(),
+        title:     hit.title.clone(),
+        published: DateTime::<Utc>::from_utc(hit.published, Utc),
+    };
+
+    // leave the downloading to the daemon, if there is one
+    if let Some(mut daemon) = daemon {
+        let title = found.title.clone().unwrap_or_else(|| found.guid.clone());
+        match daemon.request(&Request::Download{episode: found}).await? {
+            Response::Queued => eprintln!("Asked the daemon to download {}", title),
+            Response::Error{message} => bail!(message),
+            response => bail!("unexpected response from daemon: {:?}", response),
+        }
+        return Ok(ExitStatus::Queued);
+    }
+
+    let reporter = Reporter::new(OutputFormat::Human);
+    let downloads = Downloads::default();
+    let summary = download_found(db, opts, locks, wait, &downloads, &reporter, &found).await?;
+    Ok(summary.exit_status())
+}
+
+/// Downloads an episode found by a search, as fetching it would have
+///
+/// The download is registered and recorded in the history as a run of its own, with its output in
+/// the download log directory, if there is one.
+pub(crate)
+async fn download_found(
+    db:        &mut Database,
+    opts:      &Options,
+    locks:     &Locks,
+    wait:      Wait,
+    downloads: &Downloads,
+    reporter:  &Reporter,
+    found:     &FoundEpisode,
+) -> Anyhow<FetchSummary> {
+    let mut locked = FeedLocks::new(locks);
+    locked.lock(&found.feed, wait).await?;
+    let feed = match db.get_feeds(GetFeeds::One(&found.feed))?.pop() {
+        Some(feed) => feed,
+        None       => bail!("no feed named {}", found.feed),
+    };
+    let overrides = db.get_overrides(&feed.name)?;
+    let settings = FeedSettings::new(opts, &overrides);
+
+    let link: Url = found.url.parse()
+        .with_context(|| format!("{} has a bad link: {}", found.guid, found.url))?;
+    let episode = Episode {
+        meta: EpisodeMeta {
+            title:       found.title.as_deref(),
+            duration:    None,
+            length:      None,
+            description: None,
+        },
+        id:   &found.guid,
+        url:  &link,
+        when: found.published,
+    };
+    let title = episode.meta.title.unwrap_or(&found.guid);
+
+    reporter.emit(Event::Note{message: format!("Downloading {} from {}", title, feed.name)});
+    let mut summary = FetchSummary{n_feeds: 1, ..Default::default()};
+    let run = db.start_run(Utc::now(), &[&feed.name])?;
+    let downloaded = download_episode(
+        db, opts, settings, downloads, run, &feed, &episode, false, reporter, &mut summary,
+    ).await?;
+    finish_run(db, opts, run, &summary, reporter);
+
+    if downloaded {
+        reporter.emit(Event::Note{message: format!("Downloaded {}", title)});
+    }
+    Ok(summary)
+}
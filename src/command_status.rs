@@ -0,0 +1,66 @@
+
+use {
+    crate::{Anyhow, table::Table},
+    podchamp::Database,
+    chrono::prelude::*,
+};
+
+/// Shows how checking each feed has been going, flagging those that look broken or dead
+pub(crate)
+fn status(db: &Database, now: DateTime<Utc>, failures: u32, quiet_months: u32) -> Anyhow<()> {
+    let feeds = db.get_health()?;
+    if feeds.is_empty() {
+        eprintln!("No feeds. You can add one with `podchamp add`.");
+        return Ok(());
+    }
+
+    let quiet_since = now - chrono::Duration::days(30 * quiet_months as i64);
+    let date = |naive: Option<NaiveDateTime>| match naive {
+        Some(naive) => DateTime::<Utc>::from_utc(naive, Utc)
+            .with_timezone(&Local)
+            .format("%F %R")
+            .to_string(),
+        None => "-".into(),
+    };
+
+    let mut table = Table::new([
+        "FEED", "STATE", "LAST CHECKED", "LAST OK", "HTTP", "FAILURES", "LATEST EPISODE", "LAST ERROR"
+    ]);
+
+    for (feed, health) in feeds {
+        let health = match health {
+            Some(health) => health,
+            None => {
+                let state = if feed.enabled { "unchecked" } else { "paused" };
+                let mut row = vec![feed.name.to_string(), state.into()];
+                row.extend(std::iter::repeat_n("-".to_string(), 6));
+                table.push(row);
+                continue;
+            }
+        };
+
+        let quiet = health.latest_episode
+            .is_some_and(|latest| DateTime::<Utc>::from_utc(latest, Utc) < quiet_since);
+        // shout about the feeds that need attention
+        let state =
+            if !feed.enabled                           { "paused" }
+            else if health.failures as u32 >= failures { "FAILING" }
+            else if quiet                              { "QUIET" }
+            else if health.failures > 0                { "failed" }
+            else                                       { "ok" };
+
+        table.push(vec![
+            feed.name.to_string(),
+            state.into(),
+            date(Some(health.last_attempt)),
+            date(health.last_success),
+            health.last_http_status.map_or("-".into(), |status| status.to_string()),
+            health.failures.to_string(),
+            date(health.latest_episode),
+            health.last_error.as_deref().unwrap_or("-").into(),
+        ]);
+    }
+
+    table.print();
+    Ok(())
+}
@@ -0,0 +1,39 @@
+
+use {
+    crate::{Anyhow, table::Table},
+    podchamp::Database,
+};
+
+/// Lists the tags in use, and any with settings overridden, with those settings
+pub(crate)
+fn tags(db: &Database) -> Anyhow<()> {
+    let counts = db.count_tagged()?;
+    let settings = db.get_tag_settings()?;
+
+    let mut names: Vec<&str> = counts.iter().map(|(tag, _)| &tag[..])
+        .chain(settings.iter().map(|settings| &settings.tag[..]))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    if names.is_empty() {
+        eprintln!("No tags. You can tag a feed with `podchamp tag`.");
+        return Ok(());
+    }
+
+    let mut table = Table::new(["TAG", "FEEDS", "DOWNLOADER", "DATE FORMAT"]);
+    for name in names {
+        let count = counts.iter().find(|(tag, _)| tag == name).map_or(0, |(_, count)| *count);
+        let settings = settings.iter().find(|settings| settings.tag == name);
+        let setting = |value: Option<&str>| value.unwrap_or("-").to_string();
+        table.push(vec![
+            name.into(),
+            count.to_string(),
+            setting(settings.and_then(|settings| settings.downloader.as_deref())),
+            setting(settings.and_then(|settings| settings.date_format.as_deref())),
+        ]);
+    }
+    table.print();
+
+    Ok(())
+}
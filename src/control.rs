@@ -0,0 +1,340 @@
+
+use {
+    crate::{
+        Anyhow,
+        downloads::{Downloads, DownloadStatus},
+        hooks::FetchSummary,
+    },
+    anyhow::bail,
+    chrono::prelude::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap},
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+    tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+        sync::Notify,
+    },
+};
+
+/// The name of the daemon's control socket, in the runtime directory
+const SOCKET_NAME: &str = "control.sock";
+
+/// A request to the daemon, sent as one line of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(crate) enum Request {
+    /// Fetch one feed, the feeds with a tag, or every feed
+    Fetch {
+        feed:  Option<String>,
+        #[serde(default)]
+        tag:   Option<String>,
+        /// Fetch even if not due yet
+        #[serde(default)]
+        force: bool,
+        /// Fetch even if paused
+        #[serde(default)]
+        include_paused: bool,
+        /// Read older pages of the feeds
+        #[serde(default)]
+        archive: bool,
+    },
+    /// Download an episode found by a search
+    Download { episode: FoundEpisode },
+    /// List downloads in progress
+    Downloads,
+    /// Cancel a download in progress
+    Cancel { id: u64 },
+    /// Forget the polling schedule, so that every feed is checked on its interval starting now
+    #[serde(alias = "reload")]
+    Reschedule,
+    /// How the last check of one feed, or every feed, went
+    Status { feed: Option<String> },
+}
+
+/// The daemon's answer to a request, sent as one line of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub(crate) enum Response {
+    Queued,
+    Downloads { downloads: Vec<DownloadStatus> },
+    Cancelled,
+    Rescheduling,
+    Status { feeds: Vec<FeedStatus> },
+    Error { message: String },
+}
+
+/// How the daemon's last check of a feed went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FeedStatus {
+    pub feed:       String,
+    pub checked:    DateTime<Utc>,
+    pub downloaded: usize,
+    pub failed:     usize,
+    /// Why the feed couldn't be checked at all, if it couldn't
+    pub error:      Option<String>,
+}
+
+/// An episode found by a search, to be downloaded as fetching it would have
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FoundEpisode {
+    pub feed:      String,
+    pub guid:      String,
+    pub url:       String,
+    pub title:     Option<String>,
+    pub published: DateTime<Utc>,
+}
+
+/// How particular to be about fetching feeds that have been asked for
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct FetchRequest {
+    pub force:          bool,
+    pub include_paused: bool,
+    pub archive:        bool,
+}
+
+impl FetchRequest {
+    fn merge(&mut self, other: FetchRequest) {
+        self.force |= other.force;
+        self.include_paused |= other.include_paused;
+        self.archive |= other.archive;
+    }
+}
+
+/// What the daemon has been asked to do since it last looked
+#[derive(Debug, Default)]
+pub(crate) struct Requests {
+    /// Feeds to fetch
+    pub fetch:      HashMap<String, FetchRequest>,
+    /// Tags whose feeds to fetch
+    pub fetch_tags: HashMap<String, FetchRequest>,
+    /// Whether to fetch every feed
+    pub fetch_all:  Option<FetchRequest>,
+    /// Whether to forget the polling schedule
+    pub reschedule: bool,
+    /// Episodes found by searches, to download
+    pub downloads:  Vec<FoundEpisode>,
+}
+
+impl Requests {
+    /// Whether a feed with the given name and tags has been asked for, and how
+    pub fn for_feed(&self, name: &str, tags: &[String]) -> Option<FetchRequest> {
+        let requests = self.fetch.get(name).into_iter()
+            .chain(tags.iter().filter_map(|tag| self.fetch_tags.get(tag)))
+            .chain(self.fetch_all.as_ref());
+
+        let mut merged = None::<FetchRequest>;
+        for &request in requests {
+            merged.get_or_insert_with(Default::default).merge(request);
+        }
+        merged
+    }
+}
+
+struct Shared {
+    downloads: Downloads,
+    statuses:  Mutex<BTreeMap<String, FeedStatus>>,
+    requests:  Mutex<Requests>,
+    wake:      Notify,
+}
+
+/// The daemon's end of the control socket
+pub(crate) struct ControlServer {
+    path:   PathBuf,
+    shared: Arc<Shared>,
+}
+
+impl ControlServer {
+    /// Starts listening on the control socket
+    ///
+    /// A socket left behind by a daemon that's no longer running is replaced, but a live one is an
+    /// error.
+    pub fn bind(rt_path: &Path) -> Anyhow<Self> {
+        std::fs::create_dir_all(rt_path)?;
+        let path = rt_path.join(SOCKET_NAME);
+
+        let shared = Arc::new(Shared {
+            downloads: Downloads::default(),
+            statuses:  Default::default(),
+            requests:  Default::default(),
+            wake:      Notify::new(),
+        });
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::{fs::PermissionsExt as _, net::UnixStream};
+
+            if path.exists() {
+                if UnixStream::connect(&path).is_ok() {
+                    bail!("another podchamp daemon is already running (control socket {} is live)",
+                        path.display());
+                }
+                std::fs::remove_file(&path)?;
+            }
+
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, shared.clone()));
+                }
+            });
+        }
+
+        Ok(ControlServer{path, shared})
+    }
+
+    /// The registry to record the daemon's downloads in
+    pub fn downloads(&self) -> &Downloads {
+        &self.shared.downloads
+    }
+
+    /// Takes the requests received since the last call
+    pub fn take_requests(&self) -> Requests {
+        std::mem::take(&mut *self.shared.requests.lock().unwrap())
+    }
+
+    /// Resolves when there are requests to take
+    pub async fn requested(&self) {
+        self.shared.wake.notified().await
+    }
+
+    /// Notes how checking some feeds went
+    pub fn record_round(&self, feeds: &[String], summary: &FetchSummary, checked: DateTime<Utc>) {
+        let mut statuses = self.shared.statuses.lock().unwrap();
+        for feed in feeds {
+            let count = |episodes: &[crate::hooks::EpisodeSummary]| {
+                episodes.iter().filter(|ep| ep.feed == *feed).count()
+            };
+
+            statuses.insert(feed.clone(), FeedStatus {
+                feed:       feed.clone(),
+                checked,
+                downloaded: count(&summary.downloaded),
+                failed:     count(&summary.failed),
+                error:      summary.failed_feeds.iter()
+                    .find(|failure| failure.feed == *feed)
+                    .map(|failure| failure.error.clone()),
+            });
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+async fn serve(stream: tokio::net::UnixStream, shared: Arc<Shared>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => respond(&shared, request),
+            Err(e)      => Response::Error{message: format!("bad request: {}", e)},
+        };
+
+        let mut response = serde_json::to_string(&response).unwrap();
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn respond(shared: &Shared, request: Request) -> Response {
+    match request {
+        Request::Fetch{feed, tag, force, include_paused, archive} => {
+            let request = FetchRequest{force, include_paused, archive};
+            let mut requests = shared.requests.lock().unwrap();
+            let entry = match (feed, tag) {
+                (Some(feed), _)   => requests.fetch.entry(feed).or_default(),
+                (None, Some(tag)) => requests.fetch_tags.entry(tag).or_default(),
+                (None, None)      => requests.fetch_all.get_or_insert_with(Default::default),
+            };
+            entry.merge(request);
+            shared.wake.notify_one();
+            Response::Queued
+        }
+
+        Request::Download{episode} => {
+            shared.requests.lock().unwrap().downloads.push(episode);
+            shared.wake.notify_one();
+            Response::Queued
+        }
+
+        Request::Downloads => Response::Downloads{downloads: shared.downloads.list()},
+
+        Request::Cancel{id} => match shared.downloads.cancel(id) {
+            true  => Response::Cancelled,
+            false => Response::Error{message: format!("no download in progress with ID {}", id)},
+        },
+
+        Request::Reschedule => {
+            shared.requests.lock().unwrap().reschedule = true;
+            shared.wake.notify_one();
+            Response::Rescheduling
+        }
+
+        Request::Status{feed} => {
+            let statuses = shared.statuses.lock().unwrap();
+            match feed {
+                Some(feed) => match statuses.get(&feed) {
+                    Some(status) => Response::Status{feeds: vec![status.clone()]},
+                    None => Response::Error{message: format!("{} hasn't been checked yet", feed)},
+                },
+                None => Response::Status{feeds: statuses.values().cloned().collect()},
+            }
+        }
+    }
+}
+
+/// A connection to a running daemon's control socket
+pub(crate) struct ControlClient {
+    #[cfg(unix)]
+    stream: BufReader<tokio::net::UnixStream>,
+}
+
+impl ControlClient {
+    /// Connects to the daemon, if one is running
+    pub async fn connect(rt_path: &Path) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            let stream = tokio::net::UnixStream::connect(rt_path.join(SOCKET_NAME)).await.ok()?;
+            Some(ControlClient{stream: BufReader::new(stream)})
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = rt_path;
+            None
+        }
+    }
+
+    pub async fn request(&mut self, request: &Request) -> Anyhow<Response> {
+        #[cfg(unix)]
+        {
+            let mut line = serde_json::to_string(request)?;
+            line.push('\n');
+            self.stream.get_mut().write_all(line.as_bytes()).await?;
+
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("the daemon closed the control socket without answering");
+            }
+            Ok(serde_json::from_str(&line)?)
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = request;
+            bail!("control sockets are only supported on unix")
+        }
+    }
+}
@@ -1,7 +1,9 @@
 
 embed_migrations!();
 
+#[allow(non_local_definitions)]
 pub mod models;
+#[allow(non_local_definitions)]
 pub mod schema;
 
 use {
@@ -22,6 +24,8 @@ pub enum OpenDatabaseError {
     CreateDirectory(std::io::Error),
     #[error(transparent)]
     Diesel(#[from] diesel::result::ConnectionError),
+    #[error("configuring database connection")]
+    Configure(diesel::result::Error),
     #[error(transparent)]
     Migration(#[from] diesel_migrations::RunMigrationsError),
 }
@@ -32,13 +36,75 @@ impl Database {
         std::fs::create_dir_all(dir).map_err(OpenDatabaseError::CreateDirectory)?;
 
         let path = path.to_str().ok_or(OpenDatabaseError::InvalidPath)?;
-        use diesel::prelude::*;
+        use diesel::{prelude::*, connection::SimpleConnection as _};
         let conn = SqliteConnection::establish(path)?;
+
+        // several instances may be using the database at once, so let readers carry on while
+        // somebody writes, and have writers queue up rather than fail
+        conn.batch_execute("
+            pragma foreign_keys = on;
+            pragma busy_timeout = 30000;
+            pragma journal_mode = wal;
+        ").map_err(OpenDatabaseError::Configure)?;
+
         embedded_migrations::run(&conn)?;
 
         let db = Database{conn};
         Ok(db)
     }
+
+    /// Looks up the key of the feed with the given name
+    fn feed_id(&self, name: &str) -> diesel::QueryResult<i32> {
+        use{diesel::prelude::*, schema::feeds::dsl as feeds};
+        feeds::feeds
+            .filter(feeds::name.eq(name))
+            .select(feeds::id)
+            .first(&self.conn)
+    }
+}
+
+/// The keys of the feeds with the given name, as a subquery
+macro_rules! feed_ids_named {
+    ($name:expr) => {
+        schema::feeds::table
+            .filter(schema::feeds::name.eq($name))
+            .select(schema::feeds::id)
+    }
+}
+
+/// Whether an error (not including its sources) is a failure of the database itself, rather than
+/// a problem with what was asked of it
+pub fn is_database_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    if error.is::<OpenDatabaseError>() || error.is::<diesel::result::Error>() {
+        return true;
+    }
+
+    macro_rules! database_variant {
+        ($($ty:ident),* $(,)?) => {
+            $(if let Some($ty::Database(_)) = error.downcast_ref::<$ty>() { return true; })*
+        }
+    }
+
+    database_variant!(
+        AddFeedError,
+        RemoveFeedError,
+        GetFeedsError,
+        CountEpisodesError,
+        SetColumnError,
+        RenameFeedError,
+        ResetRegisterError,
+        IsEpisodeRegisteredError,
+        RegisterEpisodeError,
+        FindDuplicateError,
+        RemapEpisodeError,
+        WebSubError,
+        HealthError,
+        TagError,
+        HistoryError,
+        SearchError,
+    );
+
+    false
 }
 
 #[derive(Debug, Error)]
@@ -55,11 +121,10 @@ impl Database {
         link: &Url,
         backlog: std::num::NonZeroU32,
     ) -> Result<(), AddFeedError> {
-        let feed = models::Feed {
+        let feed = models::NewFeed {
             name: name.into(),
             uri: link.as_str().into(),
             backlog: backlog.get() as i32,
-            fetch_since: None
         };
 
         use diesel::{prelude::*, result::{Error, DatabaseErrorKind}};
@@ -108,6 +173,9 @@ pub enum GetFeedsError {
 pub enum GetFeeds<'n> {
     All,
     One(&'n str),
+    Named(&'n [String]),
+    /// The feeds with any of the names, along with those with any of the tags
+    Selected { names: &'n [String], tags: &'n [String] },
 }
 
 impl Database {
@@ -118,7 +186,17 @@ impl Database {
 
         let query = match which {
             GetFeeds::All       => feeds::feeds.into_boxed(),
-            GetFeeds::One(name) => feeds::feeds.filter(feeds::name.eq(name)).into_boxed()
+            GetFeeds::One(name) => feeds::feeds.filter(feeds::name.eq(name)).into_boxed(),
+            GetFeeds::Named(names) => feeds::feeds.filter(feeds::name.eq_any(names)).into_boxed(),
+            GetFeeds::Selected{names, tags} => {
+                use schema::tags::dsl as tagged;
+                let tagged = tagged::tags
+                    .filter(tagged::tag.eq_any(tags))
+                    .select(tagged::feed_id);
+                feeds::feeds
+                    .filter(feeds::name.eq_any(names).or(feeds::id.eq_any(tagged)))
+                    .into_boxed()
+            }
         };
 
         query.load::<models::Feed>(&self.conn)
@@ -126,6 +204,37 @@ impl Database {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum CountEpisodesError {
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Counts the episodes registered for each feed, by feed key; feeds without any are left out
+    pub fn count_episodes(&self)
+        -> Result<std::collections::HashMap<i32, i64>, CountEpisodesError>
+    {
+        use{diesel::{prelude::*, dsl::sql, sql_types::BigInt}, schema::register::dsl as register};
+        // diesel can't express an aggregate alongside the grouped column
+        let counts = register::register
+            .group_by(register::feed_id)
+            .select((register::feed_id, sql::<BigInt>("count(*)")))
+            .load::<(i32, i64)>(&self.conn)?;
+        Ok(counts.into_iter().collect())
+    }
+}
+
+/// What a feed says about itself, for telling whether two feeds are the same show
+#[derive(Debug, Clone, Copy)]
+pub struct FeedIdentity<'a> {
+    pub title:      Option<&'a str>,
+    /// The feed's `podcast:guid`
+    pub guid:       Option<&'a str>,
+    /// Where the feed's link ended up, after any redirects
+    pub final_link: &'a Url,
+}
+
 #[derive(Debug, Error)]
 pub enum SetColumnError {
     #[error("no feed named {0}")]
@@ -161,6 +270,93 @@ impl Database {
         Ok(())
     }
 
+    pub fn set_poll_interval(&mut self, feed: &str, interval: Option<std::time::Duration>)
+        -> Result<(), SetColumnError>
+    {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let secs = interval.map(|interval| interval.as_secs().min(i32::MAX as u64) as i32);
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::poll_interval.eq(secs))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_next_check(&mut self, feed: &str, when: &DateTime<Utc>)
+        -> Result<(), SetColumnError>
+    {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::next_check.eq(when.naive_utc()))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, feed: &str, enabled: bool) -> Result<(), SetColumnError> {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::enabled.eq(enabled))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether a feed skips episodes already downloaded from other feeds; `None` means the
+    /// default
+    pub fn set_dedupe(&mut self, feed: &str, dedupe: Option<bool>) -> Result<(), SetColumnError> {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::dedupe.eq(dedupe))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether a feed's older pages are read when it's fetched
+    pub fn set_archive(&mut self, feed: &str, archive: bool) -> Result<(), SetColumnError> {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::archive.eq(archive))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Records what a feed said about itself when it was last read
+    pub fn set_identity(&mut self, feed: &str, identity: &FeedIdentity<'_>)
+        -> Result<(), SetColumnError>
+    {
+        use{diesel::prelude::*, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set((
+                dsl::title.eq(identity.title),
+                dsl::guid.eq(identity.guid),
+                dsl::final_uri.eq(identity.final_link.as_str()),
+            ))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(SetColumnError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+
     pub fn set_fetch_since(&mut self, feed: &str, since: &DateTime<Utc>)
         -> Result<(), SetColumnError>
     {
@@ -176,6 +372,39 @@ impl Database {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum RenameFeedError {
+    #[error("no feed named {0}")]
+    NoSuchFeed(String),
+    #[error("feed named {0} already in database")]
+    NameTaken(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Renames a feed
+    ///
+    /// Everything else refers to feeds by key rather than by name, so the feed's history goes with
+    /// it.
+    pub fn rename_feed(&mut self, feed: &str, new_name: &str) -> Result<(), RenameFeedError> {
+        use{diesel::{prelude::*, result::{Error, DatabaseErrorKind}}, schema::feeds::dsl as dsl};
+        let n = diesel::update(dsl::feeds.filter(dsl::name.eq(feed)))
+            .set(dsl::name.eq(new_name))
+            .execute(&self.conn)
+            .map_err(|e| match e {
+                Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+                    => RenameFeedError::NameTaken(new_name.to_owned()),
+                e   => e.into(),
+            })?;
+        if n == 0 {
+            return Err(RenameFeedError::NoSuchFeed(feed.into()));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ResetRegisterError {
     #[error(transparent)]
@@ -188,14 +417,19 @@ impl Database {
 
         use schema::{register::dsl as register, feeds::dsl as feeds};
 
-        diesel::delete(register::register.filter(register::feed.eq(feed)))
-            .execute(&self.conn)?;
+        self.conn.transaction(|| {
+            diesel::delete(register::register.filter(register::feed_id.eq_any(feed_ids_named!(feed))))
+                .execute(&self.conn)?;
 
-        diesel::update(feeds::feeds.filter(feeds::name.eq(feed)))
-            .set(feeds::fetch_since.eq::<Option<NaiveDateTime>>(None))
-            .execute(&self.conn)?;
+            diesel::update(feeds::feeds.filter(feeds::name.eq(feed)))
+                .set((
+                    feeds::fetch_since.eq::<Option<NaiveDateTime>>(None),
+                    feeds::next_check.eq::<Option<NaiveDateTime>>(None),
+                ))
+                .execute(&self.conn)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
 
@@ -211,12 +445,45 @@ impl Database {
     {
         use {diesel::prelude::*, schema::register::dsl as register};
         let n: i64 = register::register
-            .filter(register::feed.eq(feed))
+            .filter(register::feed_id.eq_any(feed_ids_named!(feed)))
             .filter(register::guid.eq(guid))
             .count()
             .get_result(&self.conn)?;
         Ok(n != 0)
     }
+
+    /// Looks up an episode's registration, if it has one
+    pub fn get_registration(&self, feed: &str, guid: &str)
+        -> Result<Option<models::Registration<'static>>, IsEpisodeRegisteredError>
+    {
+        use {diesel::prelude::*, schema::register::dsl as register};
+        Ok(register::register
+            .filter(register::feed_id.eq_any(feed_ids_named!(feed)))
+            .filter(register::guid.eq(guid))
+            .first(&self.conn)
+            .optional()?)
+    }
+}
+
+/// An episode, as much as the register remembers of it
+#[derive(Debug, Clone, Copy)]
+pub struct EpisodeRecord<'a> {
+    pub guid:      &'a str,
+    pub enclosure: &'a Url,
+    pub title:     Option<&'a str>,
+    pub duration:  Option<std::time::Duration>,
+    pub length:    Option<u64>,
+    pub published: DateTime<Utc>,
+}
+
+impl EpisodeRecord<'_> {
+    fn duration_secs(&self) -> Option<i32> {
+        self.duration.and_then(|duration| duration.as_secs().try_into().ok())
+    }
+
+    fn length_bytes(&self) -> Option<i64> {
+        self.length.and_then(|length| length.try_into().ok())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -226,14 +493,20 @@ pub enum RegisterEpisodeError {
 }
 
 impl Database {
-    pub fn register_episode(&mut self, feed: &str, guid: &str)
+    pub fn register_episode(&mut self, feed: &str, episode: &EpisodeRecord<'_>)
         -> Result<(), RegisterEpisodeError>
     {
+        use diesel::prelude::*;
         let registration = models::Registration {
-            feed: feed.into(),
-            guid: guid.into()
+            feed_id:       self.feed_id(feed)?,
+            guid:          episode.guid.into(),
+            enclosure:     Some(episode.enclosure.as_str().into()),
+            enclosure_key: Some(crate::discovery::enclosure_key(episode.enclosure).into()),
+            title:         episode.title.map(Into::into),
+            duration:      episode.duration_secs(),
+            published:     Some(episode.published.naive_utc()),
+            length:        episode.length_bytes(),
         };
-        use diesel::prelude::*;
         diesel::insert_into(schema::register::table)
             .values(&registration)
             .execute(&self.conn)?;
@@ -241,3 +514,806 @@ impl Database {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum FindDuplicateError {
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Looks for another feed that has already had an episode, going by its GUID along with its
+    /// title or enclosure's link, its enclosure's link, or its title and duration together, and
+    /// gives that feed's name
+    ///
+    /// A GUID alone isn't enough, as some feeds use GUIDs like `1` or `episode-1`.
+    pub fn find_duplicate(&self, feed: &str, episode: &EpisodeRecord<'_>)
+        -> Result<Option<String>, FindDuplicateError>
+    {
+        use {diesel::prelude::*, schema::{register::dsl as register, feeds::dsl as feeds}};
+        let others = register::register
+            .inner_join(feeds::feeds)
+            .filter(register::feed_id.ne(self.feed_id(feed)?))
+            .select(feeds::name);
+
+        let key = crate::discovery::enclosure_key(episode.enclosure);
+        let by_guid = others
+            .filter(register::guid.eq(episode.guid))
+            // an untitled episode's NULL title matches nothing
+            .filter(register::enclosure_key.eq(&key).or(register::title.eq(episode.title)))
+            .first(&self.conn)
+            .optional()?;
+        if by_guid.is_some() {
+            return Ok(by_guid);
+        }
+
+        let by_enclosure = others
+            .filter(register::enclosure_key.eq(&key))
+            .first(&self.conn)
+            .optional()?;
+        if by_enclosure.is_some() {
+            return Ok(by_enclosure);
+        }
+
+        match (episode.title, episode.duration_secs()) {
+            (Some(title), Some(duration)) => Ok(others
+                .filter(register::title.eq(title))
+                .filter(register::duration.eq(duration))
+                .first(&self.conn)
+                .optional()?),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RemapEpisodeError {
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Finds the GUIDs a feed's episode might have been registered under before, going by its
+    /// enclosure's link, or its title along with a date within a day of its own
+    pub fn find_earlier_guids(&self, feed: &str, episode: &EpisodeRecord<'_>)
+        -> Result<Vec<String>, RemapEpisodeError>
+    {
+        use {diesel::prelude::*, schema::register::dsl as register};
+        let day = chrono::Duration::days(1);
+        let earliest = (episode.published - day).naive_utc();
+        let latest = (episode.published + day).naive_utc();
+        let key = crate::discovery::enclosure_key(episode.enclosure);
+
+        let same_title = register::title.eq(episode.title)
+            .and(register::published.between(earliest, latest));
+        Ok(register::register
+            .filter(register::feed_id.eq(self.feed_id(feed)?))
+            .filter(register::guid.ne(episode.guid))
+            .filter(register::enclosure_key.eq(key).or(same_title))
+            .select(register::guid)
+            .load(&self.conn)?)
+    }
+
+    /// Updates an episode's registration with what's known about it now, moving it over to the
+    /// episode's GUID if it was registered under another
+    pub fn update_registration(&mut self, feed: &str, old_guid: &str, episode: &EpisodeRecord<'_>)
+        -> Result<(), RemapEpisodeError>
+    {
+        use {diesel::prelude::*, schema::register::dsl as register};
+        diesel::update(register::register
+                .filter(register::feed_id.eq(self.feed_id(feed)?))
+                .filter(register::guid.eq(old_guid)))
+            .set((
+                register::guid.eq(episode.guid),
+                register::enclosure.eq(episode.enclosure.as_str()),
+                register::enclosure_key.eq(crate::discovery::enclosure_key(episode.enclosure)),
+                register::title.eq(episode.title),
+                register::duration.eq(episode.duration_secs()),
+                register::published.eq(episode.published.naive_utc()),
+                register::length.eq(episode.length_bytes()),
+            ))
+            .execute(&self.conn)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WebSubError {
+    #[error("no subscription for feed {0}")]
+    NoSuchSubscription(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Records the hub that a feed advertises
+    ///
+    /// If the hub or topic has changed, any existing subscription is forgotten, so that a new one
+    /// will be made.
+    pub fn set_websub_hub(&mut self, feed: &str, discovery: &crate::websub::Discovery)
+        -> Result<(), WebSubError>
+    {
+        use{diesel::prelude::*, schema::websub::dsl as websub};
+        let hub = discovery.hub.as_str();
+        let topic = discovery.topic.as_str();
+
+        self.conn.transaction(|| {
+            let feed_id = self.feed_id(feed)?;
+            let unchanged: i64 = websub::websub
+                .filter(websub::feed_id.eq(feed_id))
+                .filter(websub::hub.eq(hub))
+                .filter(websub::topic.eq(topic))
+                .count()
+                .get_result(&self.conn)?;
+            if unchanged != 0 {
+                return Ok(());
+            }
+
+            let subscription = models::Subscription {
+                feed_id,
+                hub:           hub.into(),
+                topic:         topic.into(),
+                callback_id:   None,
+                secret:        None,
+                lease_expires: None,
+                renew_at:      None,
+            };
+
+            diesel::replace_into(schema::websub::table)
+                .values(&subscription)
+                .execute(&self.conn)?;
+            Ok(())
+        })
+    }
+
+    /// Gets every subscription, along with the name of its feed
+    pub fn get_websub_subscriptions(&self)
+        -> Result<Vec<(String, models::Subscription<'static>)>, WebSubError>
+    {
+        use{diesel::prelude::*, schema::{feeds, websub}};
+        Ok(websub::table
+            .inner_join(feeds::table)
+            .select((feeds::name, websub::all_columns))
+            .load(&self.conn)?)
+    }
+
+    pub fn set_websub_callback(&mut self, feed: &str, callback_id: &str, secret: &str)
+        -> Result<(), WebSubError>
+    {
+        use{diesel::prelude::*, schema::websub::dsl as websub};
+        let n = diesel::update(websub::websub.filter(websub::feed_id.eq_any(feed_ids_named!(feed))))
+            .set((websub::callback_id.eq(callback_id), websub::secret.eq(secret)))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(WebSubError::NoSuchSubscription(feed.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Records a lease granted by a hub, or with `None`, that there is no lease
+    pub fn set_websub_lease(&mut self,
+        feed:    &str,
+        expires: Option<DateTime<Utc>>,
+        renew:   Option<DateTime<Utc>>)
+        -> Result<(), WebSubError>
+    {
+        use{diesel::prelude::*, schema::websub::dsl as websub};
+        let n = diesel::update(websub::websub.filter(websub::feed_id.eq_any(feed_ids_named!(feed))))
+            .set((
+                websub::lease_expires.eq(expires.map(|t| t.naive_utc())),
+                websub::renew_at.eq(renew.map(|t| t.naive_utc())),
+            ))
+            .execute(&self.conn)?;
+        if n == 0 {
+            return Err(WebSubError::NoSuchSubscription(feed.into()));
+        }
+
+        Ok(())
+    }
+}
+
+/// What happened when a feed was checked
+#[derive(Debug, Clone, Copy)]
+pub struct CheckOutcome<'a> {
+    pub at:             DateTime<Utc>,
+    /// The HTTP status of the response, if there was one
+    pub http_status:    Option<u16>,
+    /// Why the check failed, if it did
+    pub error:          Option<&'a str>,
+    pub latest_episode: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Updates a feed's health record with the outcome of a check
+    pub fn record_check(&mut self, feed: &str, outcome: &CheckOutcome<'_>)
+        -> Result<(), HealthError>
+    {
+        use{diesel::prelude::*, schema::health::dsl as health};
+        self.conn.transaction(|| {
+            let feed_id = self.feed_id(feed)?;
+            let previous: Option<models::Health> = health::health
+                .filter(health::feed_id.eq(feed_id))
+                .first(&self.conn)
+                .optional()?;
+
+            let at = outcome.at.naive_utc();
+            let record = match (previous, outcome.error) {
+                (previous, None) => models::Health {
+                    feed_id,
+                    last_attempt:     at,
+                    last_success:     Some(at),
+                    last_http_status: outcome.http_status.map(i32::from),
+                    last_error:       None,
+                    failures:         0,
+                    latest_episode:   outcome.latest_episode.map(|t| t.naive_utc())
+                        .or(previous.and_then(|previous| previous.latest_episode)),
+                },
+
+                (previous, Some(error)) => models::Health {
+                    feed_id,
+                    last_attempt:     at,
+                    last_http_status: outcome.http_status.map(i32::from),
+                    last_error:       Some(error.into()),
+                    failures:         previous.as_ref().map_or(0, |previous| previous.failures) + 1,
+                    last_success:     previous.as_ref().and_then(|previous| previous.last_success),
+                    latest_episode:   previous.as_ref().and_then(|previous| previous.latest_episode),
+                },
+            };
+
+            diesel::replace_into(schema::health::table)
+                .values(&record)
+                .execute(&self.conn)?;
+            Ok(())
+        })
+    }
+
+    /// Gets every feed, along with its health record if it has ever been checked
+    pub fn get_health(&self)
+        -> Result<Vec<(models::Feed<'static>, Option<models::Health<'static>>)>, HealthError>
+    {
+        use{diesel::prelude::*, schema::feeds::dsl as feeds};
+        Ok(feeds::feeds
+            .left_join(schema::health::table)
+            .order(feeds::name)
+            .load(&self.conn)?)
+    }
+}
+
+/// Settings that the feeds with some tag use in place of the usual ones
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub downloader:  Option<String>,
+    pub date_format: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("no feed named {0}")]
+    NoSuchFeed(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Looks up the key of the feed with the given name, or says there's no such feed
+    fn tagged_feed_id(&self, feed: &str) -> Result<i32, TagError> {
+        use diesel::prelude::*;
+        self.feed_id(feed).optional()?
+            .ok_or_else(|| TagError::NoSuchFeed(feed.into()))
+    }
+
+    /// Tags a feed, ignoring tags it already has
+    pub fn add_tags(&mut self, feed: &str, tags: &[String]) -> Result<(), TagError> {
+        use diesel::prelude::*;
+        self.conn.transaction(|| {
+            let feed_id = self.tagged_feed_id(feed)?;
+            for tag in tags {
+                diesel::insert_or_ignore_into(schema::tags::table)
+                    .values(&models::Tagging{feed_id, tag: tag.into()})
+                    .execute(&self.conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Takes tags off a feed, ignoring tags it doesn't have
+    pub fn remove_tags(&mut self, feed: &str, tags: &[String]) -> Result<(), TagError> {
+        use{diesel::prelude::*, schema::tags::dsl as tagged};
+        let feed_id = self.tagged_feed_id(feed)?;
+        diesel::delete(tagged::tags
+                .filter(tagged::feed_id.eq(feed_id))
+                .filter(tagged::tag.eq_any(tags)))
+            .execute(&self.conn)?;
+        Ok(())
+    }
+
+    /// Gets each feed's tags, by feed key, in alphabetical order; feeds without any are left out
+    pub fn get_tags(&self)
+        -> Result<std::collections::HashMap<i32, Vec<String>>, TagError>
+    {
+        use{diesel::prelude::*, schema::tags::dsl as tagged};
+        let taggings = tagged::tags
+            .order((tagged::feed_id, tagged::tag))
+            .load::<models::Tagging>(&self.conn)?;
+
+        let mut tags = std::collections::HashMap::<i32, Vec<String>>::new();
+        for tagging in taggings {
+            tags.entry(tagging.feed_id).or_default().push(tagging.tag.into_owned());
+        }
+        Ok(tags)
+    }
+
+    /// Counts the feeds with each tag, in alphabetical order of tag
+    pub fn count_tagged(&self) -> Result<Vec<(String, i64)>, TagError> {
+        use{diesel::{prelude::*, dsl::sql, sql_types::BigInt}, schema::tags::dsl as tagged};
+        Ok(tagged::tags
+            .group_by(tagged::tag)
+            .select((tagged::tag, sql::<BigInt>("count(*)")))
+            .order(tagged::tag)
+            .load(&self.conn)?)
+    }
+
+    /// Gets the settings overridden for every tag that has any
+    pub fn get_tag_settings(&self) -> Result<Vec<models::TagSettings<'static>>, TagError> {
+        use{diesel::prelude::*, schema::tag_settings::dsl as settings};
+        Ok(settings::tag_settings
+            .order(settings::tag)
+            .load(&self.conn)?)
+    }
+
+    /// Works out the settings a feed's tags override
+    ///
+    /// Where tags disagree, the one that comes first alphabetically wins.
+    pub fn get_overrides(&self, feed: &str) -> Result<Overrides, TagError> {
+        use{diesel::prelude::*, schema::{tags::dsl as tagged, tag_settings::dsl as settings}};
+        let feed_id = self.tagged_feed_id(feed)?;
+        let tags = tagged::tags
+            .filter(tagged::feed_id.eq(feed_id))
+            .select(tagged::tag);
+        let rows = settings::tag_settings
+            .filter(settings::tag.eq_any(tags))
+            .order(settings::tag)
+            .load::<models::TagSettings>(&self.conn)?;
+
+        Ok(Overrides {
+            downloader:  rows.iter().find_map(|row| row.downloader.as_deref()).map(Into::into),
+            date_format: rows.iter().find_map(|row| row.date_format.as_deref()).map(Into::into),
+        })
+    }
+
+    /// Sets or, with `None`, clears the downloader used for feeds with a tag
+    pub fn set_tag_downloader(&mut self, tag: &str, downloader: Option<&str>)
+        -> Result<(), TagError>
+    {
+        use{diesel::prelude::*, schema::tag_settings::dsl as settings};
+        self.conn.transaction(|| {
+            self.ensure_tag_settings(tag)?;
+            diesel::update(settings::tag_settings.filter(settings::tag.eq(tag)))
+                .set(settings::downloader.eq(downloader))
+                .execute(&self.conn)?;
+            Ok(())
+        })
+    }
+
+    /// Sets or, with `None`, clears the date format used for feeds with a tag
+    pub fn set_tag_date_format(&mut self, tag: &str, date_format: Option<&str>)
+        -> Result<(), TagError>
+    {
+        use{diesel::prelude::*, schema::tag_settings::dsl as settings};
+        self.conn.transaction(|| {
+            self.ensure_tag_settings(tag)?;
+            diesel::update(settings::tag_settings.filter(settings::tag.eq(tag)))
+                .set(settings::date_format.eq(date_format))
+                .execute(&self.conn)?;
+            Ok(())
+        })
+    }
+
+    fn ensure_tag_settings(&self, tag: &str) -> diesel::QueryResult<()> {
+        use diesel::prelude::*;
+        let row = models::TagSettings{tag: tag.into(), downloader: None, date_format: None};
+        diesel::insert_or_ignore_into(schema::tag_settings::table)
+            .values(&row)
+            .execute(&self.conn)?;
+        Ok(())
+    }
+}
+
+/// A download, as it's to be remembered in the history
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadLog<'a> {
+    pub guid:        &'a str,
+    pub title:       Option<&'a str>,
+    pub url:         &'a Url,
+    /// The downloader that was run
+    pub command:     &'a str,
+    pub started:     DateTime<Utc>,
+    pub duration:    std::time::Duration,
+    pub exit_status: Option<i32>,
+    pub error:       Option<&'a str>,
+    /// The end of the downloader's output
+    pub stderr_tail: Option<&'a str>,
+    /// Where the downloader's output was logged
+    pub log_file:    Option<&'a str>,
+}
+
+/// Which part of the history to look at
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryFilter<'a> {
+    pub feed:        Option<&'a str>,
+    pub since:       Option<DateTime<Utc>>,
+    /// Only failed downloads, or runs that didn't entirely succeed
+    pub failed_only: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("no feed named {0}")]
+    NoSuchFeed(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    fn history_feed_id(&self, feed: &str) -> Result<i32, HistoryError> {
+        use diesel::prelude::*;
+        self.feed_id(feed).optional()?.ok_or_else(|| HistoryError::NoSuchFeed(feed.into()))
+    }
+
+    /// Records the start of a fetch, returning the key to record its downloads under
+    pub fn start_run(&mut self, started: DateTime<Utc>, feeds: &[&str])
+        -> Result<i32, HistoryError>
+    {
+        use diesel::{prelude::*, dsl::sql, sql_types::Integer};
+        let run = models::NewRun{started: started.naive_utc()};
+        Ok(self.conn.transaction(|| {
+            diesel::insert_into(schema::runs::table)
+                .values(&run)
+                .execute(&self.conn)?;
+            let run_id = diesel::select(sql::<Integer>("last_insert_rowid()"))
+                .get_result(&self.conn)?;
+
+            for feed in feeds {
+                // a feed removed since the fetch began has no history to keep
+                if let Some(feed_id) = self.feed_id(feed).optional()? {
+                    diesel::insert_or_ignore_into(schema::run_feeds::table)
+                        .values(&models::RunFeed{run_id, feed_id})
+                        .execute(&self.conn)?;
+                }
+            }
+
+            Ok::<_, diesel::result::Error>(run_id)
+        })?)
+    }
+
+    /// Records how a fetch went: `ok`, `partial` or `failed`
+    pub fn finish_run(&mut self, run: i32, finished: DateTime<Utc>, outcome: &str)
+        -> Result<(), HistoryError>
+    {
+        use{diesel::prelude::*, schema::runs::dsl as runs};
+        diesel::update(runs::runs.find(run))
+            .set((runs::finished.eq(finished.naive_utc()), runs::outcome.eq(outcome)))
+            .execute(&self.conn)?;
+        Ok(())
+    }
+
+    pub fn log_download(&mut self, run: i32, feed: &str, download: &DownloadLog<'_>)
+        -> Result<(), HistoryError>
+    {
+        use diesel::prelude::*;
+        let row = models::NewLoggedDownload {
+            run_id:      run,
+            feed_id:     self.history_feed_id(feed)?,
+            guid:        download.guid.into(),
+            title:       download.title.map(Into::into),
+            url:         download.url.as_str().into(),
+            command:     download.command.into(),
+            started:     download.started.naive_utc(),
+            duration:    download.duration.as_millis().try_into().unwrap_or(i32::MAX),
+            exit_status: download.exit_status,
+            error:       download.error.map(Into::into),
+            stderr_tail: download.stderr_tail.map(Into::into),
+            log_file:    download.log_file.map(Into::into),
+        };
+        diesel::insert_into(schema::download_history::table)
+            .values(&row)
+            .execute(&self.conn)?;
+        Ok(())
+    }
+
+    /// Forgets the runs that started before `before`, and all but the latest `keep`, along with
+    /// their downloads
+    pub fn prune_history(&mut self, before: DateTime<Utc>, keep: u32)
+        -> Result<(), HistoryError>
+    {
+        use{diesel::prelude::*, schema::runs::dsl as runs};
+        Ok(self.conn.transaction(|| {
+            diesel::delete(runs::runs.filter(runs::started.lt(before.naive_utc())))
+                .execute(&self.conn)?;
+
+            let newest_dropped: Option<i32> = runs::runs
+                .select(runs::id)
+                .order(runs::id.desc())
+                .offset(keep.into())
+                .first(&self.conn)
+                .optional()?;
+            if let Some(newest_dropped) = newest_dropped {
+                diesel::delete(runs::runs.filter(runs::id.le(newest_dropped)))
+                    .execute(&self.conn)?;
+            }
+
+            Ok::<_, diesel::result::Error>(())
+        })?)
+    }
+
+    /// Gets the runs in the history, oldest first, with the names of the feeds each covered and how
+    /// many downloads each attempted
+    pub fn get_runs(&self, filter: HistoryFilter<'_>)
+        -> Result<Vec<(models::Run<'static>, Vec<String>, i64)>, HistoryError>
+    {
+        use {
+            diesel::{prelude::*, dsl::sql, sql_types::BigInt},
+            schema::{
+                runs::dsl as runs, run_feeds::dsl as run_feeds, feeds::dsl as feeds,
+                download_history::dsl as history,
+            },
+        };
+
+        let mut query = runs::runs.order(runs::id).into_boxed();
+        if let Some(feed) = filter.feed {
+            let covered = run_feeds::run_feeds
+                .filter(run_feeds::feed_id.eq(self.history_feed_id(feed)?))
+                .select(run_feeds::run_id);
+            query = query.filter(runs::id.eq_any(covered));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(runs::started.ge(since.naive_utc()));
+        }
+        if filter.failed_only {
+            query = query.filter(runs::outcome.ne("ok").or(runs::outcome.is_null()));
+        }
+        let runs: Vec<models::Run> = query.load(&self.conn)?;
+
+        let counts: std::collections::HashMap<i32, i64> = history::download_history
+            .group_by(history::run_id)
+            .select((history::run_id, sql::<BigInt>("count(*)")))
+            .load::<(i32, i64)>(&self.conn)?
+            .into_iter()
+            .collect();
+
+        let mut names = std::collections::HashMap::<i32, Vec<String>>::new();
+        let covered = run_feeds::run_feeds
+            .inner_join(feeds::feeds)
+            .select((run_feeds::run_id, feeds::name))
+            .order(feeds::name)
+            .load::<(i32, String)>(&self.conn)?;
+        for (run, name) in covered {
+            names.entry(run).or_default().push(name);
+        }
+
+        Ok(runs.into_iter()
+            .map(|run| {
+                let count = counts.get(&run.id).copied().unwrap_or(0);
+                let names = names.remove(&run.id).unwrap_or_default();
+                (run, names, count)
+            })
+            .collect())
+    }
+
+    /// Gets the downloads in the history, oldest first, with the names of their feeds
+    pub fn get_download_history(&self, filter: HistoryFilter<'_>)
+        -> Result<Vec<(models::LoggedDownload<'static>, String)>, HistoryError>
+    {
+        use{diesel::prelude::*, schema::{download_history::dsl as history, feeds::dsl as feeds}};
+
+        let mut query = history::download_history
+            .inner_join(feeds::feeds)
+            .select((schema::download_history::all_columns, feeds::name))
+            .order(history::id)
+            .into_boxed();
+        if let Some(feed) = filter.feed {
+            query = query.filter(history::feed_id.eq(self.history_feed_id(feed)?));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(history::started.ge(since.naive_utc()));
+        }
+        if filter.failed_only {
+            query = query.filter(history::error.is_not_null());
+        }
+
+        Ok(query.load(&self.conn)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SearchError {
+    #[error("nothing to search for")]
+    EmptyQuery,
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+impl Database {
+    /// Stores the titles and show notes of a feed's episodes, for searching
+    pub fn index_episodes(&mut self, feed: &str, episodes: &[crate::Episode<'_>])
+        -> Result<(), SearchError>
+    {
+        use diesel::{prelude::*, sql_types::{Integer, Nullable, Text, Timestamp}};
+        let feed_id = self.feed_id(feed)?;
+        Ok(self.conn.transaction(|| {
+            for episode in episodes {
+                let description = episode.meta.description.map(crate::search::strip_html);
+                // leave unchanged episodes alone, rather than have them indexed all over again
+                diesel::sql_query("
+                    insert into episodes(feed_id, guid, title, description, published, url)
+                    values (?, ?, ?, ?, ?, ?)
+                    on conflict(feed_id, guid) do update set
+                        title = excluded.title,
+                        description = excluded.description,
+                        published = excluded.published,
+                        url = excluded.url
+                    where title is not excluded.title
+                        or description is not excluded.description
+                        or published is not excluded.published
+                        or url is not excluded.url
+                ")
+                    .bind::<Integer, _>(feed_id)
+                    .bind::<Text, _>(episode.id)
+                    .bind::<Nullable<Text>, _>(episode.meta.title)
+                    .bind::<Nullable<Text>, _>(description)
+                    .bind::<Timestamp, _>(episode.when.naive_utc())
+                    .bind::<Text, _>(episode.url.as_str())
+                    .execute(&self.conn)?;
+            }
+            Ok::<_, diesel::result::Error>(())
+        })?)
+    }
+
+    /// Finds the episodes whose titles or show notes have every word of `query` in them, best
+    /// matches first, optionally only from some feeds
+    pub fn search(&self, query: &str, feed_ids: Option<&[i32]>, limit: u32)
+        -> Result<Vec<models::SearchHit>, SearchError>
+    {
+        use diesel::{prelude::*, sql_types::{BigInt, Text}};
+        let expression = crate::search::match_expression(query).ok_or(SearchError::EmptyQuery)?;
+        // the keys are numbers from the database, so they're safe to put in the query as they are
+        let only_feeds = match feed_ids {
+            None      => String::new(),
+            Some(ids) => {
+                let ids: Vec<String> = ids.iter().map(ToString::to_string).collect();
+                format!("and episodes.feed_id in ({})", ids.join(", "))
+            }
+        };
+
+        Ok(diesel::sql_query(format!("
+            select feeds.name as feed, episodes.guid, episodes.title, episodes.published,
+                episodes.url,
+                snippet(episode_search, 1, '[', ']', '…', 12) as snippet
+            from episode_search
+            join episodes on episodes.id = episode_search.rowid
+            join feeds on feeds.id = episodes.feed_id
+            where episode_search match ? {}
+            order by bm25(episode_search, 10.0, 1.0)
+            limit ?
+        ", only_feeds))
+            .bind::<Text, _>(expression)
+            .bind::<BigInt, _>(i64::from(limit))
+            .load(&self.conn)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone as _};
+
+    /// A database of its own for each test, in a directory that's removed afterwards
+    struct Scratch {
+        dir: std::path::PathBuf,
+        db:  Database,
+    }
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("podchamp-db-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let db = Database::open(&dir.join("podchamp.sqlite")).unwrap();
+            Scratch{dir, db}
+        }
+
+        fn add(&mut self, name: &str) {
+            let link = Url::parse(&format!("https://example.com/{}.xml", name)).unwrap();
+            self.db.add_feed(name, &link, std::num::NonZeroU32::new(1).unwrap()).unwrap();
+        }
+
+        fn names(&self) -> Vec<String> {
+            let mut names: Vec<String> = self.db.get_feeds(GetFeeds::All).unwrap().into_iter()
+                .map(|feed| feed.name.into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn renamed_feed_keeps_its_episodes() {
+        let mut scratch = Scratch::new("rename");
+        scratch.add("news");
+        scratch.add("comedy");
+
+        let enclosure = Url::parse("https://example.com/1.mp3").unwrap();
+        scratch.db.register_episode("news", &EpisodeRecord {
+            guid:      "ep1",
+            enclosure: &enclosure,
+            title:     Some("Episode 1"),
+            duration:  None,
+            length:    None,
+            published: Utc::now(),
+        }).unwrap();
+
+        scratch.db.rename_feed("news", "daily").unwrap();
+        assert_eq!(scratch.names(), ["comedy", "daily"]);
+        assert!(scratch.db.get_registration("daily", "ep1").unwrap().is_some());
+        assert!(scratch.db.get_registration("news", "ep1").unwrap().is_none());
+    }
+
+    #[test]
+    fn rename_refuses_taken_and_unknown_names() {
+        let mut scratch = Scratch::new("rename-taken");
+        scratch.add("news");
+        scratch.add("comedy");
+
+        let taken = scratch.db.rename_feed("news", "comedy");
+        assert!(matches!(taken, Err(RenameFeedError::NameTaken(name)) if name == "comedy"));
+        assert_eq!(scratch.names(), ["comedy", "news"]);
+
+        let missing = scratch.db.rename_feed("sports", "football");
+        assert!(matches!(missing, Err(RenameFeedError::NoSuchFeed(name)) if name == "sports"));
+    }
+
+    #[test]
+    fn earlier_guids_by_enclosure_or_title() {
+        let mut scratch = Scratch::new("earlier");
+        scratch.add("show");
+        let day = |n| Utc.ymd(2026, 10, n).and_hms(12, 0, 0);
+        let old = Url::parse("https://old.example.com/1.mp3").unwrap();
+        let other = Url::parse("https://old.example.com/2.mp3").unwrap();
+        for (guid, title, enclosure, published) in [
+            ("old1", "One", &old, day(1)),
+            ("old2", "Two", &other, day(5)),
+        ] {
+            scratch.db.register_episode("show", &EpisodeRecord {
+                guid, enclosure, title: Some(title), duration: None, length: None, published,
+            }).unwrap();
+        }
+
+        let moved = Url::parse("https://new.example.com/1.mp3").unwrap();
+        let earlier = |guid, title, enclosure, published| {
+            scratch.db.find_earlier_guids("show", &EpisodeRecord {
+                guid, enclosure, title, duration: None, length: None, published,
+            }).unwrap()
+        };
+
+        // the same enclosure, whatever it's called now
+        assert_eq!(earlier("new1", Some("Renamed"), &old, day(9)), ["old1"]);
+        // the same title, within a day
+        assert_eq!(earlier("new1", Some("One"), &moved, day(2)), ["old1"]);
+        assert!(earlier("new1", Some("One"), &moved, day(3)).is_empty());
+        // no title is nothing to go on
+        assert!(earlier("new1", None, &moved, day(1)).is_empty());
+        // an episode isn't an earlier version of itself
+        assert!(earlier("old1", Some("One"), &old, day(1)).is_empty());
+    }
+}
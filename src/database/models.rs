@@ -22,19 +22,178 @@ use {
 //    }
 //}
 
-#[derive(Queryable, Insertable)]
-#[table_name="feeds"]
+#[derive(Queryable)]
 pub struct Feed<'a> {
+    pub id:          i32,
     pub name:        Cow<'a, str>,
     pub uri:         Cow<'a, str>,
     pub backlog:     i32,
     pub fetch_since: Option<NaiveDateTime>,
+    /// Seconds between checks in daemon mode, if not the default
+    pub poll_interval: Option<i32>,
+    /// When the feed is next due to be checked
+    pub next_check:  Option<NaiveDateTime>,
+    /// Whether the feed is fetched, or paused
+    pub enabled:     bool,
+    /// The feed's title, as of when it was last read
+    pub title:       Option<Cow<'a, str>>,
+    /// The feed's `podcast:guid`, if it has one
+    pub guid:        Option<Cow<'a, str>>,
+    /// Where the feed's link redirected to, when it was last read
+    pub final_uri:   Option<Cow<'a, str>>,
+    /// Whether to skip episodes already downloaded from other feeds, if not the default
+    pub dedupe:      Option<bool>,
+    /// Whether to read older pages of the feed, for feeds that split their episodes across pages
+    pub archive:     bool,
+}
+
+#[derive(Insertable)]
+#[table_name="feeds"]
+pub struct NewFeed<'a> {
+    pub name:    Cow<'a, str>,
+    pub uri:     Cow<'a, str>,
+    pub backlog: i32,
 }
 
 #[derive(Queryable, Insertable)]
 #[table_name="register"]
 pub struct Registration<'a> {
-    pub feed: Cow<'a, str>,
-    pub guid: Cow<'a, str>,
+    pub feed_id:       i32,
+    pub guid:          Cow<'a, str>,
+    pub enclosure:     Option<Cow<'a, str>>,
+    /// The enclosure's link, boiled down for comparing with other feeds' episodes
+    pub enclosure_key: Option<Cow<'a, str>>,
+    pub title:         Option<Cow<'a, str>>,
+    /// The episode's length in seconds
+    pub duration:      Option<i32>,
+    pub published:     Option<NaiveDateTime>,
+    /// The enclosure's size in bytes, as the feed gave it
+    pub length:        Option<i64>,
+}
+
+
+#[derive(Queryable, Insertable)]
+#[table_name="websub"]
+pub struct Subscription<'a> {
+    pub feed_id:       i32,
+    pub hub:           Cow<'a, str>,
+    pub topic:         Cow<'a, str>,
+    /// The path component identifying this subscription's callback URL
+    pub callback_id:   Option<Cow<'a, str>>,
+    pub secret:        Option<Cow<'a, str>>,
+    pub lease_expires: Option<NaiveDateTime>,
+    pub renew_at:      Option<NaiveDateTime>,
+}
+
+/// How checking a feed has been going
+#[derive(Queryable, Insertable)]
+#[table_name="health"]
+pub struct Health<'a> {
+    pub feed_id:          i32,
+    pub last_attempt:     NaiveDateTime,
+    pub last_success:     Option<NaiveDateTime>,
+    pub last_http_status: Option<i32>,
+    pub last_error:       Option<Cow<'a, str>>,
+    /// How many checks in a row have failed
+    pub failures:         i32,
+    /// The date of the newest episode seen in the feed
+    pub latest_episode:   Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name="tags"]
+pub struct Tagging<'a> {
+    pub feed_id: i32,
+    pub tag:     Cow<'a, str>,
 }
 
+/// Settings overridden for the feeds with a tag
+#[derive(Queryable, Insertable)]
+#[table_name="tag_settings"]
+pub struct TagSettings<'a> {
+    pub tag:         Cow<'a, str>,
+    pub downloader:  Option<Cow<'a, str>>,
+    pub date_format: Option<Cow<'a, str>>,
+}
+
+/// A fetch, as remembered in the history
+#[derive(Queryable)]
+pub struct Run<'a> {
+    pub id:       i32,
+    pub started:  NaiveDateTime,
+    /// When the fetch finished, unless it never did
+    pub finished: Option<NaiveDateTime>,
+    /// `ok`, `partial` or `failed`
+    pub outcome:  Option<Cow<'a, str>>,
+}
+
+#[derive(Insertable)]
+#[table_name="runs"]
+pub struct NewRun {
+    pub started: NaiveDateTime,
+}
+
+/// One of the feeds a fetch covered
+#[derive(Insertable)]
+#[table_name="run_feeds"]
+pub struct RunFeed {
+    pub run_id:  i32,
+    pub feed_id: i32,
+}
+
+/// A download, as remembered in the history
+#[derive(Queryable)]
+pub struct LoggedDownload<'a> {
+    pub id:          i32,
+    pub run_id:      i32,
+    pub feed_id:     i32,
+    pub guid:        Cow<'a, str>,
+    pub title:       Option<Cow<'a, str>>,
+    pub url:         Cow<'a, str>,
+    pub command:     Cow<'a, str>,
+    pub started:     NaiveDateTime,
+    /// How long the download took, in milliseconds
+    pub duration:    i32,
+    pub exit_status: Option<i32>,
+    pub error:       Option<Cow<'a, str>>,
+    /// The end of the downloader's output: what it wrote to its standard error, and to its
+    /// standard output too if that was logged
+    pub stderr_tail: Option<Cow<'a, str>>,
+    /// Where the downloader's output was logged
+    pub log_file:    Option<Cow<'a, str>>,
+}
+
+#[derive(Insertable)]
+#[table_name="download_history"]
+pub struct NewLoggedDownload<'a> {
+    pub run_id:      i32,
+    pub feed_id:     i32,
+    pub guid:        Cow<'a, str>,
+    pub title:       Option<Cow<'a, str>>,
+    pub url:         Cow<'a, str>,
+    pub command:     Cow<'a, str>,
+    pub started:     NaiveDateTime,
+    pub duration:    i32,
+    pub exit_status: Option<i32>,
+    pub error:       Option<Cow<'a, str>>,
+    pub stderr_tail: Option<Cow<'a, str>>,
+    pub log_file:    Option<Cow<'a, str>>,
+}
+
+/// An episode found by a search
+#[derive(QueryableByName)]
+pub struct SearchHit {
+    #[sql_type = "diesel::sql_types::Text"]
+    pub feed:      String,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub guid:      String,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub title:     Option<String>,
+    #[sql_type = "diesel::sql_types::Timestamp"]
+    pub published: NaiveDateTime,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub url:       String,
+    /// The part of the show notes that matched, with the matching words in `[brackets]`
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub snippet:   Option<String>,
+}
@@ -1,22 +1,138 @@
 table! {
-    feeds (name) {
+    episodes (id) {
+        id -> Integer,
+        feed_id -> Integer,
+        guid -> Text,
+        title -> Nullable<Text>,
+        description -> Nullable<Text>,
+        published -> Timestamp,
+        url -> Text,
+    }
+}
+
+table! {
+    feeds (id) {
+        id -> Integer,
         name -> Text,
         uri -> Text,
         backlog -> Integer,
         fetch_since -> Nullable<Timestamp>,
+        poll_interval -> Nullable<Integer>,
+        next_check -> Nullable<Timestamp>,
+        enabled -> Bool,
+        title -> Nullable<Text>,
+        guid -> Nullable<Text>,
+        final_uri -> Nullable<Text>,
+        dedupe -> Nullable<Bool>,
+        archive -> Bool,
+    }
+}
+
+table! {
+    register (feed_id, guid) {
+        feed_id -> Integer,
+        guid -> Text,
+        enclosure -> Nullable<Text>,
+        enclosure_key -> Nullable<Text>,
+        title -> Nullable<Text>,
+        duration -> Nullable<Integer>,
+        published -> Nullable<Timestamp>,
+        length -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    websub (feed_id) {
+        feed_id -> Integer,
+        hub -> Text,
+        topic -> Text,
+        callback_id -> Nullable<Text>,
+        secret -> Nullable<Text>,
+        lease_expires -> Nullable<Timestamp>,
+        renew_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    health (feed_id) {
+        feed_id -> Integer,
+        last_attempt -> Timestamp,
+        last_success -> Nullable<Timestamp>,
+        last_http_status -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
+        failures -> Integer,
+        latest_episode -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    runs (id) {
+        id -> Integer,
+        started -> Timestamp,
+        finished -> Nullable<Timestamp>,
+        outcome -> Nullable<Text>,
+    }
+}
+
+table! {
+    run_feeds (run_id, feed_id) {
+        run_id -> Integer,
+        feed_id -> Integer,
     }
 }
 
 table! {
-    register (feed, guid) {
-        feed -> Text,
+    download_history (id) {
+        id -> Integer,
+        run_id -> Integer,
+        feed_id -> Integer,
         guid -> Text,
+        title -> Nullable<Text>,
+        url -> Text,
+        command -> Text,
+        started -> Timestamp,
+        duration -> Integer,
+        exit_status -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        stderr_tail -> Nullable<Text>,
+        log_file -> Nullable<Text>,
+    }
+}
+
+table! {
+    tags (feed_id, tag) {
+        feed_id -> Integer,
+        tag -> Text,
+    }
+}
+
+table! {
+    tag_settings (tag) {
+        tag -> Text,
+        downloader -> Nullable<Text>,
+        date_format -> Nullable<Text>,
     }
 }
 
-joinable!(register -> feeds (feed));
+joinable!(download_history -> feeds (feed_id));
+joinable!(download_history -> runs (run_id));
+joinable!(episodes -> feeds (feed_id));
+joinable!(health -> feeds (feed_id));
+joinable!(register -> feeds (feed_id));
+joinable!(run_feeds -> feeds (feed_id));
+joinable!(run_feeds -> runs (run_id));
+joinable!(tags -> feeds (feed_id));
+joinable!(websub -> feeds (feed_id));
 
 allow_tables_to_appear_in_same_query!(
+    download_history,
+    episodes,
     feeds,
+    health,
     register,
+    run_feeds,
+    runs,
+    tag_settings,
+    tags,
+    websub,
 );
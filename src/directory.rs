@@ -0,0 +1,292 @@
+
+use {
+    crate::{Anyhow, Options},
+    podchamp::websub::encode_hex,
+    anyhow::{anyhow, bail, Context as _},
+    url::Url,
+};
+
+/// A podcast's entry in a directory, as identified by a link to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Entry {
+    /// An Apple Podcasts ID, from a link like `https://podcasts.apple.com/us/podcast/name/id123`
+    Apple(u64),
+    /// A Podcast Index feed ID, from a link like `https://podcastindex.org/podcast/123` or
+    /// `podcastindex:123`
+    PodcastIndexId(u64),
+    /// A `podcast:guid`, from a link like `podcastindex:917393e3-1b1e-5cef-ace4-edaa54e1f810`
+    PodcastIndexGuid(String),
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Apple(id)              => write!(f, "Apple Podcasts ID {}", id),
+            Entry::PodcastIndexId(id)     => write!(f, "Podcast Index ID {}", id),
+            Entry::PodcastIndexGuid(guid) => write!(f, "podcast GUID {}", guid),
+        }
+    }
+}
+
+/// A feed found in a directory
+pub(crate) struct Resolved {
+    pub link:  Url,
+    pub title: Option<String>,
+}
+
+impl Entry {
+    /// Works out which directory entry a link is to, if it's to one
+    pub fn recognise(link: &Url) -> Option<Self> {
+        let number = |s: &str| s.parse::<u64>().ok();
+
+        if link.scheme() == "podcastindex" {
+            let id = link.path().trim();
+            return match number(id) {
+                Some(id) => Some(Entry::PodcastIndexId(id)),
+                None     => Some(Entry::PodcastIndexGuid(id.into())),
+            };
+        }
+
+        let host = link.host_str()?;
+        let last = link.path_segments()?.rfind(|segment| !segment.is_empty())?;
+        match host {
+            "podcasts.apple.com" | "itunes.apple.com"
+                => last.strip_prefix("id").and_then(number).map(Entry::Apple),
+            "podcastindex.org" | "www.podcastindex.org"
+                => number(last).map(Entry::PodcastIndexId),
+            _   => None,
+        }
+    }
+
+    /// Looks the entry up in its directory, to find its feed
+    pub async fn resolve(&self, opts: &Options) -> Anyhow<Resolved> {
+        let resolved = match self {
+            Entry::Apple(id)
+                => lookup_apple(opts, *id).await?,
+            Entry::PodcastIndexId(id)
+                => lookup_podcast_index(opts, "podcasts/byfeedid", ("id", &id.to_string())).await?,
+            Entry::PodcastIndexGuid(guid)
+                => lookup_podcast_index(opts, "podcasts/byguid", ("guid", guid)).await?,
+        };
+
+        resolved.ok_or_else(|| anyhow!("no feed found for {}", self))
+    }
+}
+
+async fn lookup_apple(opts: &Options, id: u64) -> Anyhow<Option<Resolved>> {
+    let response = reqwest::Client::new()
+        .get(opts.apple_lookup_url.clone())
+        .query(&[("id", &id.to_string()[..]), ("entity", "podcast")])
+        .send().await
+        .and_then(reqwest::Response::error_for_status)
+        .context("looking up the feed in Apple Podcasts")?
+        .bytes().await?;
+    let response: serde_json::Value = serde_json::from_slice(&response)?;
+
+    let results = response["results"].as_array().map_or(&[][..], |results| &results[..]);
+    Ok(results.iter().find_map(|result| Some(Resolved {
+        link:  result["feedUrl"].as_str()?.parse().ok()?,
+        title: result["collectionName"].as_str().map(String::from),
+    })))
+}
+
+async fn lookup_podcast_index(opts: &Options, endpoint: &str, query: (&str, &str))
+    -> Anyhow<Option<Resolved>>
+{
+    let (key, secret) = match (&opts.podcast_index_key, &opts.podcast_index_secret) {
+        (Some(key), Some(secret)) => (key, secret),
+        _ => bail!("looking up feeds in Podcast Index needs an API key and secret; \
+            set PODCHAMP_PODCAST_INDEX_KEY and PODCHAMP_PODCAST_INDEX_SECRET"),
+    };
+
+    // each request is signed with the secret and the time
+    let now = chrono::Utc::now().timestamp().to_string();
+    let signature = {
+        use sha1::Digest as _;
+        encode_hex(&sha1::Sha1::digest(format!("{}{}{}", key, secret, now).as_bytes()))
+    };
+
+    let url = format!("{}/{}", opts.podcast_index_url.as_str().trim_end_matches('/'), endpoint);
+    let response = reqwest::Client::new()
+        .get(url)
+        .query(&[query])
+        .header(reqwest::header::USER_AGENT, concat!("podchamp/", env!("CARGO_PKG_VERSION")))
+        .header("X-Auth-Key", key)
+        .header("X-Auth-Date", &now)
+        .header(reqwest::header::AUTHORIZATION, signature)
+        .send().await
+        .and_then(reqwest::Response::error_for_status)
+        .context("looking up the feed in Podcast Index")?
+        .bytes().await?;
+    let response: serde_json::Value = serde_json::from_slice(&response)?;
+
+    let feed = &response["feed"];
+    Ok(feed["url"].as_str().and_then(|link| link.parse().ok()).map(|link| Resolved {
+        link,
+        title: feed["title"].as_str().map(String::from),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        hyper::{Body, Request, Response},
+        std::{
+            collections::HashMap,
+            convert::Infallible,
+            sync::{Arc, Mutex},
+        },
+    };
+
+    fn entry(link: &str) -> Option<Entry> {
+        Entry::recognise(&Url::parse(link).unwrap())
+    }
+
+    #[test]
+    fn recognises_directory_links() {
+        let guid = "917393e3-1b1e-5cef-ace4-edaa54e1f810";
+        assert_eq!(entry("https://podcasts.apple.com/us/podcast/my-brother/id367330921"),
+            Some(Entry::Apple(367330921)));
+        assert_eq!(entry("https://itunes.apple.com/podcast/id367330921/?mt=2"),
+            Some(Entry::Apple(367330921)));
+        assert_eq!(entry("https://podcastindex.org/podcast/920666"),
+            Some(Entry::PodcastIndexId(920666)));
+        assert_eq!(entry("podcastindex:920666"), Some(Entry::PodcastIndexId(920666)));
+        assert_eq!(entry(&format!("podcastindex:{}", guid)),
+            Some(Entry::PodcastIndexGuid(guid.into())));
+
+        assert_eq!(entry("https://podcasts.apple.com/us/browse"), None);
+        assert_eq!(entry("https://podcastindex.org/search?q=x"), None);
+        assert_eq!(entry("https://example.com/podcast/id123"), None);
+    }
+
+    /// A request the stand-in directory received
+    #[derive(Debug)]
+    struct Seen {
+        path:    String,
+        query:   HashMap<String, String>,
+        headers: HashMap<String, String>,
+    }
+
+    /// Serves canned answers for both directories on localhost, recording each request
+    fn stand_in_directory() -> (Url, Arc<Mutex<Vec<Seen>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let make_service = hyper::service::make_service_fn({
+            let seen = seen.clone();
+            move |_| {
+                let seen = seen.clone();
+                async move {
+                    Ok::<_, Infallible>(hyper::service::service_fn(move |req: Request<Body>| {
+                        let query: HashMap<String, String> = url::form_urlencoded::parse(
+                                req.uri().query().unwrap_or_default().as_bytes())
+                            .into_owned()
+                            .collect();
+                        let body = match req.uri().path() {
+                            "/lookup" if query.get("id").map(String::as_str) == Some("42") => {
+                                r#"{"resultCount": 1, "results": [{"collectionName": "Apple Show",
+                                    "feedUrl": "https://example.com/apple.xml"}]}"#
+                            }
+                            "/lookup" => r#"{"resultCount": 0, "results": []}"#,
+                            "/pi/podcasts/byfeedid" | "/pi/podcasts/byguid" => {
+                                r#"{"status": "true", "feed": {"title": "Indexed Show",
+                                    "url": "https://example.com/indexed.xml"}}"#
+                            }
+                            _ => "{}",
+                        };
+
+                        seen.lock().unwrap().push(Seen {
+                            path:    req.uri().path().into(),
+                            query,
+                            headers: req.headers().iter()
+                                .map(|(name, value)| {
+                                    (name.to_string(), value.to_str().unwrap().to_owned())
+                                })
+                                .collect(),
+                        });
+                        async move { Ok::<_, Infallible>(Response::new(Body::from(body))) }
+                    }))
+                }
+            }
+        });
+
+        tokio::spawn(hyper::Server::from_tcp(listener).unwrap().serve(make_service));
+        (base, seen)
+    }
+
+    fn options(base: &Url, credentials: &[&str]) -> Options {
+        use clap::Parser as _;
+        // the runtime directory's default is worked out even when it's not needed
+        if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+            std::env::set_var("XDG_RUNTIME_DIR", std::env::temp_dir());
+        }
+
+        let apple = base.join("lookup").unwrap();
+        let index = base.join("pi").unwrap();
+        let mut args = vec!["podchamp", "--apple-lookup-url", apple.as_str()];
+        args.extend(["--podcast-index-url", index.as_str()]);
+        args.extend(credentials);
+        args.push("ls");
+        Options::try_parse_from(args).unwrap()
+    }
+
+    #[tokio::test]
+    async fn resolves_apple_ids() {
+        let (base, seen) = stand_in_directory();
+        let opts = options(&base, &[]);
+
+        let resolved = Entry::Apple(42).resolve(&opts).await.unwrap();
+        assert_eq!(resolved.link.as_str(), "https://example.com/apple.xml");
+        assert_eq!(resolved.title.as_deref(), Some("Apple Show"));
+
+        {
+            let seen = seen.lock().unwrap();
+            assert_eq!(seen[0].path, "/lookup");
+            assert_eq!(seen[0].query["entity"], "podcast");
+        }
+
+        let error = Entry::Apple(7).resolve(&opts).await.err().unwrap();
+        assert_eq!(error.to_string(), "no feed found for Apple Podcasts ID 7");
+    }
+
+    #[tokio::test]
+    async fn resolves_podcast_index_entries_with_signed_requests() {
+        let (base, seen) = stand_in_directory();
+        let credentials = ["--podcast-index-key", "KEY", "--podcast-index-secret", "SECRET"];
+        let opts = options(&base, &credentials);
+
+        let resolved = Entry::PodcastIndexId(920666).resolve(&opts).await.unwrap();
+        assert_eq!(resolved.link.as_str(), "https://example.com/indexed.xml");
+        assert_eq!(resolved.title.as_deref(), Some("Indexed Show"));
+
+        let guid = "917393e3-1b1e-5cef-ace4-edaa54e1f810";
+        Entry::PodcastIndexGuid(guid.into()).resolve(&opts).await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].path, "/pi/podcasts/byfeedid");
+        assert_eq!(seen[0].query["id"], "920666");
+        assert_eq!(seen[1].path, "/pi/podcasts/byguid");
+        assert_eq!(seen[1].query["guid"], guid);
+
+        for request in &*seen {
+            use sha1::Digest as _;
+            let headers = &request.headers;
+            let date = &headers["x-auth-date"];
+            let expected = sha1::Sha1::digest(format!("KEYSECRET{}", date).as_bytes());
+            assert_eq!(headers["x-auth-key"], "KEY");
+            assert_eq!(headers["authorization"], encode_hex(&expected));
+            assert!(headers["user-agent"].starts_with("podchamp/"));
+        }
+    }
+
+    #[tokio::test]
+    async fn podcast_index_needs_credentials() {
+        let (base, seen) = stand_in_directory();
+        let opts = options(&base, &[]);
+        assert!(Entry::PodcastIndexId(1).resolve(&opts).await.is_err());
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use url::Url;
+
+/// Turns a `feed:`, `itpc:`, `pcast:` or `podcast:` link into the plain web link it stands for
+///
+/// These schemes either replace `http` (`itpc://example.com/rss`) or wrap a whole link
+/// (`feed:https://example.com/rss`). Other links are left as they are.
+pub fn normalise_scheme(link: &Url) -> Result<Url, url::ParseError> {
+    if !matches!(link.scheme(), "feed" | "itpc" | "pcast" | "podcast") {
+        return Ok(link.clone());
+    }
+
+    let rest = &link.as_str()[link.scheme().len() + 1 ..];
+    match Url::parse(rest) {
+        Ok(inner) if matches!(inner.scheme(), "http" | "https") => Ok(inner),
+        _ => Url::parse(&format!("http:{}", rest)),
+    }
+}
+
+/// A feed that a web page says is an alternative version of itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alternate {
+    pub link:  Url,
+    pub title: Option<String>,
+}
+
+/// Whether a response looks like a web page rather than a feed
+pub fn is_html(content_type: Option<&str>, body: &[u8]) -> bool {
+    if let Some(content_type) = content_type {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        if mime == "text/html" || mime == "application/xhtml+xml" {
+            return true;
+        }
+    }
+
+    let start = String::from_utf8_lossy(&body[.. body.len().min(512)]).to_ascii_lowercase();
+    let start = start.trim_start_matches('\u{feff}').trim_start();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Finds the RSS and Atom feeds a web page advertises with
+/// `<link rel="alternate" type="application/rss+xml">` (or `atom+xml`), in the order they appear
+///
+/// Relative links are resolved against `page`.
+pub fn find_alternates(html: &[u8], page: &Url) -> Vec<Alternate> {
+    // web pages are rarely well-formed enough for an XML parser, so just pick out the `<link>` tags
+    let html = String::from_utf8_lossy(html);
+    let lower = html.to_ascii_lowercase();
+    let mut alternates = Vec::new();
+
+    for (start, _) in lower.match_indices("<link") {
+        let tag = &html[start + 5 ..];
+        if !tag.starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+
+        let tag = &tag[.. tag.find('>').unwrap_or(tag.len())];
+        let attributes = parse_attributes(tag);
+        let attribute = |name: &str| attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| &value[..]);
+
+        let alternate = attribute("rel").is_some_and(|rel| {
+            rel.split_ascii_whitespace().any(|rel| rel.eq_ignore_ascii_case("alternate"))
+        });
+        let feed = attribute("type").is_some_and(|kind| {
+            let kind = kind.trim().to_ascii_lowercase();
+            kind == "application/rss+xml" || kind == "application/atom+xml"
+        });
+        let link = attribute("href").and_then(|href| page.join(href.trim()).ok());
+        if let (true, true, Some(link)) = (alternate, feed, link) {
+            if !alternates.iter().any(|known: &Alternate| known.link == link) {
+                let title = attribute("title").map(String::from);
+                alternates.push(Alternate{link, title});
+            }
+        }
+    }
+
+    alternates
+}
+
+/// Splits the inside of an HTML tag into (lowercased name, value) pairs, with values unquoted and
+/// the common character references decoded
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+
+    while !rest.is_empty() {
+        let name_end = rest.find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[.. name_end].to_ascii_lowercase();
+        rest = rest[name_end ..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remainder) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1 ..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[.. end], inner.get(end + 1 ..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(|c: char| c.is_ascii_whitespace()).unwrap_or(after.len());
+                    (&after[.. end], &after[end ..])
+                }
+            };
+            value = decode_references(raw);
+            rest = remainder.trim_start();
+        }
+        else if name.is_empty() {
+            // a stray `/` or the like
+            rest = rest[1 ..].trim_start();
+            continue;
+        }
+
+        attributes.push((name, value));
+    }
+
+    attributes
+}
+
+fn decode_references(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Query parameters that only track where a link was shared, and don't change what it points to
+const TRACKING_PARAMETERS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "igshid", "mc_cid", "mc_eid", "_hsenc", "_hsmi",
+];
+
+/// Boils a link down to the parts that say which feed it is, for comparing with other links
+///
+/// `http` and `https`, a leading `www.`, a trailing slash, the fragment, tracking parameters like
+/// `utm_source`, and the order of the other parameters all make no difference.
+pub fn comparable(link: &Url) -> String {
+    let host = link.host_str().unwrap_or_default().to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let port = link.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let path = link.path().trim_end_matches('/');
+
+    let mut query: Vec<(String, String)> = link.query_pairs()
+        .filter(|(key, _)| {
+            !key.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&&key.to_ascii_lowercase()[..])
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    query.sort();
+    let query: Vec<String> = query.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+
+    match query.is_empty() {
+        true  => format!("{}{}{}", host, port, path),
+        false => format!("{}{}{}?{}", host, port, path, query.join("&")),
+    }
+}
+
+/// Services that count downloads by having enclosure links go through them first, as the start of
+/// the links they pass through, with `*` standing for any one part of the path
+const MEASUREMENT_PREFIXES: &[&str] = &[
+    "dts.podtrac.com/redirect.mp3",
+    "podtrac.com/pts/redirect.mp3",
+    "chtbl.com/track/*",
+    "pdst.fm/e",
+    "op3.dev/e",
+    "pscrb.fm/rss/p",
+    "arttrk.com/p/*",
+    "pfx.vpixl.com/*",
+    "claritaspod.com/measure",
+    "mgln.ai/e/*",
+];
+
+/// Boils an episode's enclosure link down for comparing with other feeds' episodes
+///
+/// This is [`comparable`], with any download-counting services the link goes through first
+/// taken off the front, since different feeds often send the same file through different ones.
+pub fn enclosure_key(link: &Url) -> String {
+    let mut key = comparable(link);
+    while let Some(inner) = strip_measurement_prefix(&key) {
+        let inner = inner.strip_prefix("https://").or_else(|| inner.strip_prefix("http://"))
+            .unwrap_or(inner);
+        key = inner.strip_prefix("www.").unwrap_or(inner).to_owned();
+    }
+
+    key
+}
+
+fn strip_measurement_prefix(key: &str) -> Option<&str> {
+    MEASUREMENT_PREFIXES.iter().find_map(|prefix| {
+        let mut rest = key;
+        for part in prefix.split('/') {
+            let (head, tail) = rest.split_once('/')?;
+            if part != "*" && !part.eq_ignore_ascii_case(head) {
+                return None;
+            }
+            rest = tail;
+        }
+        (!rest.is_empty()).then_some(rest)
+    })
+}
+
+/// Finds the link to a feed's next page of older episodes, for feeds that split their episodes
+/// across pages: an RFC 5005 `next` or `prev-archive` link, in an HTTP `Link` header or in a
+/// `<link>` (or `<atom:link>`) outside the feed's entries
+///
+/// Relative links are resolved against `page`.
+pub fn next_page(xml: &[u8], link_header: Option<&str>, page: &Url) -> Option<Url> {
+    use quick_xml::events::Event;
+    let is_paging = |rel: &str| rel.split_ascii_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case("next") || rel.eq_ignore_ascii_case("prev-archive"));
+
+    let in_header = link_header.into_iter()
+        .flat_map(crate::websub::parse_link_header)
+        .find(|(_, rel)| is_paging(rel))
+        .and_then(|(target, _)| page.join(&target).ok());
+    if in_header.is_some() {
+        return in_header;
+    }
+
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut in_entry = 0;
+
+    loop {
+        let event = match reader.read_event(&mut buf) {
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(event) => event,
+        };
+
+        match &event {
+            Event::Start(e) if matches!(e.local_name(), b"item" | b"entry") => in_entry += 1,
+            Event::End(e)   if matches!(e.local_name(), b"item" | b"entry") => in_entry -= 1,
+            Event::Start(e) | Event::Empty(e) if in_entry == 0 && e.local_name() == b"link" => {
+                let mut rel = None;
+                let mut href = None;
+                for attr in e.attributes().flatten() {
+                    let value = attr.unescape_and_decode_value(&reader).ok();
+                    match attr.key {
+                        b"rel"  => rel = value,
+                        b"href" => href = value,
+                        _ => { }
+                    }
+                }
+
+                if rel.as_deref().is_some_and(is_paging) {
+                    if let Some(link) = href.and_then(|href| page.join(href.trim()).ok()) {
+                        return Some(link);
+                    }
+                }
+            }
+            _ => { }
+        }
+
+        buf.clear();
+    }
+}
+
+/// Finds a feed's `<podcast:guid>`, which names the show independently of where its feed lives
+pub fn podcast_guid(xml: &[u8]) -> Option<String> {
+    use quick_xml::events::Event;
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut in_guid = false;
+    let mut in_entry = 0;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(Event::Start(e)) if matches!(e.local_name(), b"item" | b"entry") => in_entry += 1,
+            Ok(Event::End(e))   if matches!(e.local_name(), b"item" | b"entry") => in_entry -= 1,
+            Ok(Event::Start(e)) => in_guid = in_entry == 0 && e.name() == b"podcast:guid",
+            Ok(Event::Text(text)) if in_guid => {
+                let guid = text.unescape_and_decode(&reader).ok()?;
+                let guid = guid.trim();
+                return (!guid.is_empty()).then(|| guid.to_owned());
+            }
+            Ok(Event::End(_)) => in_guid = false,
+            Ok(_) => { }
+        }
+
+        buf.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(link: &str) -> Url {
+        Url::parse(link).unwrap()
+    }
+
+    #[test]
+    fn podcast_schemes() {
+        let normalised = |link| normalise_scheme(&url(link)).unwrap().to_string();
+        assert_eq!(normalised("itpc://example.com/rss"), "http://example.com/rss");
+        assert_eq!(normalised("pcast://example.com/rss"), "http://example.com/rss");
+        assert_eq!(normalised("podcast://example.com/rss?x=1"), "http://example.com/rss?x=1");
+        assert_eq!(normalised("feed://example.com/rss"), "http://example.com/rss");
+        assert_eq!(normalised("feed:https://example.com/rss"), "https://example.com/rss");
+        assert_eq!(normalised("https://example.com/rss"), "https://example.com/rss");
+    }
+
+    #[test]
+    fn html_detection() {
+        assert!(is_html(Some("text/html; charset=utf-8"), b"<rss/>"));
+        assert!(is_html(None, b"\xef\xbb\xbf\n  <!DOCTYPE html><html></html>"));
+        assert!(!is_html(Some("application/rss+xml"), b"<?xml version=\"1.0\"?><rss/>"));
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(parse_attributes(r#" REL="alternate" type='application/rss+xml' href=/feed
+                title="Tom &amp; Jerry&#39;s" async /"#), [
+            ("rel".to_owned(), "alternate".to_owned()),
+            ("type".to_owned(), "application/rss+xml".to_owned()),
+            ("href".to_owned(), "/feed".to_owned()),
+            ("title".to_owned(), "Tom & Jerry's".to_owned()),
+            ("async".to_owned(), String::new()),
+        ]);
+        assert_eq!(parse_attributes(r#"href = "x"#), [("href".to_owned(), "x".to_owned())]);
+    }
+
+    #[test]
+    fn alternates() {
+        let page = url("https://example.com/show/");
+        let html = br#"<!doctype html><html><head>
+            <link rel="stylesheet" href="/style.css">
+            <LINK REL="Alternate" TYPE="application/rss+xml" title="Audio" href="feed.xml">
+            <link rel="alternate" type="application/atom+xml" href="https://cdn.example/atom">
+            <link rel="alternate" type="application/rss+xml" href="/show/feed.xml">
+            <link rel="alternate" type="text/html" hreflang="fr" href="/fr/">
+            <linkage rel="alternate" type="application/rss+xml" href="/nope">
+        </head></html>"#;
+        assert_eq!(find_alternates(html, &page), [
+            Alternate{link: url("https://example.com/show/feed.xml"), title: Some("Audio".into())},
+            Alternate{link: url("https://cdn.example/atom"), title: None},
+        ]);
+        assert!(find_alternates(b"<html><body>no feeds</body></html>", &page).is_empty());
+    }
+
+    #[test]
+    fn comparable_links() {
+        let key = |link| comparable(&url(link));
+        assert_eq!(key("https://www.Example.com/feed/?b=2&a=1&utm_source=x&fbclid=y#top"),
+            "example.com/feed?a=1&b=2");
+        assert_eq!(key("http://example.com/feed"), key("https://www.example.com/feed/"));
+        assert_eq!(key("http://example.com:8080/feed"), "example.com:8080/feed");
+        assert_ne!(key("https://example.com/feed?show=1"), key("https://example.com/feed?show=2"));
+    }
+
+    #[test]
+    fn podcast_guids() {
+        let xml = br#"<rss xmlns:podcast="https://podcastindex.org/namespace/1.0"><channel>
+            <item><podcast:guid>not-this-one</podcast:guid></item>
+            <podcast:guid> 917393e3-1b1e-5cef-ace4-edaa54e1f810 </podcast:guid>
+        </channel></rss>"#;
+        assert_eq!(podcast_guid(xml).as_deref(), Some("917393e3-1b1e-5cef-ace4-edaa54e1f810"));
+        assert_eq!(podcast_guid(b"<rss><channel><podcast:guid/></channel></rss>"), None);
+        assert_eq!(podcast_guid(b"<rss><channel><title>x</title></channel></rss>"), None);
+    }
+
+    #[test]
+    fn enclosure_keys() {
+        let key = |link| enclosure_key(&url(link));
+        let chained = concat!("https://dts.podtrac.com/redirect.mp3/",
+            "chtbl.com/track/ABC12/cdn.example.com/ep1.mp3");
+        assert_eq!(key(chained), "cdn.example.com/ep1.mp3");
+        assert_eq!(key("https://pdst.fm/e/www.cdn.example.com/ep1.mp3?utm_source=feed"),
+            "cdn.example.com/ep1.mp3");
+        assert_eq!(key("https://op3.dev/e/https://cdn.example.com/ep1.mp3"),
+            "cdn.example.com/ep1.mp3");
+        assert_eq!(key("http://cdn.example.com/ep1.mp3"), "cdn.example.com/ep1.mp3");
+
+        // a prefix only counts as a whole path segment, with something after it
+        assert_eq!(key("https://pdst.fm/episodes/ep1.mp3"), "pdst.fm/episodes/ep1.mp3");
+        assert_eq!(key("https://chtbl.com/track/ABC12"), "chtbl.com/track/ABC12");
+    }
+}
@@ -0,0 +1,117 @@
+
+use {
+    chrono::prelude::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    },
+    tokio::sync::Notify,
+};
+
+/// A download in progress, as reported over the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DownloadStatus {
+    pub id:           u64,
+    pub feed:         String,
+    pub title:        Option<String>,
+    pub url:          String,
+    pub started:      DateTime<Utc>,
+    /// The downloader is a black box, so this is as much progress as there is to report
+    pub elapsed_secs: i64,
+}
+
+struct Active {
+    feed:    String,
+    title:   Option<String>,
+    url:     String,
+    started: DateTime<Utc>,
+    cancel:  Arc<Notify>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id:  u64,
+    active:   BTreeMap<u64, Active>,
+    /// Whether to finish what's in progress, but start nothing new
+    stopping: bool,
+}
+
+/// The downloads this process has in progress, so that they can be listed and cancelled
+#[derive(Clone, Default)]
+pub(crate) struct Downloads {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Downloads {
+    /// Registers a download, which stays listed until the ticket is dropped
+    pub fn start(&self, feed: &str, title: Option<&str>, url: &str) -> Ticket {
+        let cancel = Arc::new(Notify::new());
+        let mut registry = self.registry.lock().unwrap();
+        registry.next_id += 1;
+        let id = registry.next_id;
+        registry.active.insert(id, Active {
+            feed:    feed.into(),
+            title:   title.map(Into::into),
+            url:     url.into(),
+            started: Utc::now(),
+            cancel:  cancel.clone(),
+        });
+
+        Ticket{downloads: self.clone(), id, cancel}
+    }
+
+    pub fn list(&self) -> Vec<DownloadStatus> {
+        let now = Utc::now();
+        self.registry.lock().unwrap().active.iter()
+            .map(|(id, active)| DownloadStatus {
+                id:           *id,
+                feed:         active.feed.clone(),
+                title:        active.title.clone(),
+                url:          active.url.clone(),
+                started:      active.started,
+                elapsed_secs: (now - active.started).num_seconds(),
+            })
+            .collect()
+    }
+
+    /// Lets the downloads in progress finish, but asks that no more be started
+    pub fn stop(&self) {
+        self.registry.lock().unwrap().stopping = true;
+    }
+
+    /// Whether new downloads, and new feeds, should be left for another time
+    pub fn stopping(&self) -> bool {
+        self.registry.lock().unwrap().stopping
+    }
+
+    /// Asks a download to stop, returning false if there's no such download
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.registry.lock().unwrap().active.get(&id) {
+            Some(active) => { active.cancel.notify_one(); true }
+            None         => false,
+        }
+    }
+}
+
+/// A download's place in the registry
+pub(crate) struct Ticket {
+    downloads: Downloads,
+    id:        u64,
+    cancel:    Arc<Notify>,
+}
+
+impl Ticket {
+    /// Resolves when the download is cancelled
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = self.downloads.registry.lock() {
+            registry.active.remove(&self.id);
+        }
+    }
+}
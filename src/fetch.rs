@@ -1,13 +1,18 @@
 
 use {
-    crate::models::Feed,
+    crate::{EpisodeRecord, models::Feed},
     chrono::prelude::*,
     url::Url,
 };
 
 #[derive(Debug, Clone)]
 pub struct EpisodeMeta<'a> {
-    pub title: Option<&'a str>
+    pub title:       Option<&'a str>,
+    pub duration:    Option<std::time::Duration>,
+    /// The enclosure's size in bytes
+    pub length:      Option<u64>,
+    /// The show notes, usually in HTML
+    pub description: Option<&'a str>,
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +23,20 @@ pub struct Episode<'a> {
     pub when: DateTime<Utc>,
 }
 
+impl<'a> Episode<'a> {
+    /// What the register remembers of the episode
+    pub fn record(&self) -> EpisodeRecord<'a> {
+        EpisodeRecord {
+            guid:      self.id,
+            enclosure: self.url,
+            title:     self.meta.title,
+            duration:  self.meta.duration,
+            length:    self.meta.length,
+            published: self.when,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FeedIndex<'a>(Vec<Episode<'a>>);
 
@@ -41,6 +60,18 @@ impl<'a> std::iter::FromIterator<Episode<'a>> for FeedIndex<'a> {
     }
 }
 
+impl<'a> FeedIndex<'a> {
+    /// Puts the pages of a feed together, newest page first, keeping only the first of any
+    /// episodes that turn up on more than one
+    pub fn merge(pages: impl IntoIterator<Item = FeedIndex<'a>>) -> Self {
+        let mut seen = std::collections::HashSet::new();
+        pages.into_iter()
+            .flat_map(|page| page.0)
+            .filter(|ep| seen.insert(ep.id))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FetchPlan<'a> {
     pub episodes:        &'a [Episode<'a>],
@@ -70,14 +101,148 @@ fn plan_fetch<'a>(feed: &Feed, index: &'a FeedIndex<'a>) -> FetchPlan<'a> {
         }
     };
 
-    // find the part of the list newer than the threshold
-    let split_index = match index.binary_search_by_key(&threshold, |ep| ep.when) {
-        Ok(at) => at + 1, // inclusive range
-        Err(at) => at
-    };
+    // find the part of the list newer than the threshold, inclusive. it's sorted newest first
+    let split_index = index.partition_point(|ep| ep.when >= threshold);
 
     let episodes = &index[..split_index];
-    let set_fetch_since = update_db.then(|| threshold);
+    let set_fetch_since = update_db.then_some(threshold);
     FetchPlan{episodes, set_fetch_since}
 }
 
+
+/// Whether episodes go back as far as [`plan_fetch`] looks, so that any older pages of the feed
+/// wouldn't change the plan
+fn goes_back(feed: &Feed, n_episodes: usize, oldest: Option<DateTime<Utc>>) -> bool {
+    let oldest = match oldest {
+        Some(oldest) => oldest,
+        None         => return false,
+    };
+
+    n_episodes >= (feed.backlog as usize).max(1) && feed.fetch_since
+        .map(|naive| DateTime::<Utc>::from_utc(naive, Utc))
+        .is_none_or(|since| oldest <= since)
+}
+
+/// How far back the pages of a feed read so far go, kept up to date as each page is read rather
+/// than by merging them all again
+#[derive(Debug, Default)]
+pub struct ArchiveReach {
+    seen:   std::collections::HashSet<String>,
+    oldest: Option<DateTime<Utc>>,
+}
+
+impl ArchiveReach {
+    /// Adds the next page, counting only episodes that weren't on an earlier one, as
+    /// [`FeedIndex::merge`] does
+    pub fn add(&mut self, page: &FeedIndex<'_>) {
+        for ep in page.iter() {
+            if self.seen.insert(ep.id.to_owned()) {
+                self.oldest = Some(self.oldest.map_or(ep.when, |oldest| oldest.min(ep.when)));
+            }
+        }
+    }
+
+    /// Whether the pages go back as far as [`plan_fetch`] looks
+    pub fn reaches_back(&self, feed: &Feed) -> bool {
+        goes_back(feed, self.seen.len(), self.oldest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(backlog: i32, fetch_since: Option<DateTime<Utc>>) -> Feed<'static> {
+        Feed {
+            id:            1,
+            name:          "test".into(),
+            uri:           "https://example.com/feed.xml".into(),
+            backlog,
+            fetch_since:   fetch_since.map(|since| since.naive_utc()),
+            poll_interval: None,
+            next_check:    None,
+            enabled:       true,
+            title:         None,
+            guid:          None,
+            final_uri:     None,
+            dedupe:        None,
+            archive:       false,
+        }
+    }
+
+    fn day(n: u32) -> DateTime<Utc> {
+        Utc.ymd(2026, 10, n).and_hms(12, 0, 0)
+    }
+
+    /// Episodes `ep1` to `ep<n>`, published on the 1st to the nth
+    fn index(link: &Url, n: u32) -> FeedIndex<'_> {
+        let ids = ["ep1", "ep2", "ep3", "ep4", "ep5", "ep6", "ep7", "ep8", "ep9"];
+        (1 ..= n)
+            .map(|i| Episode {
+                meta: EpisodeMeta{title: None, duration: None, length: None, description: None},
+                id:   ids[i as usize - 1],
+                url:  link,
+                when: day(i),
+            })
+            .collect()
+    }
+
+    /// Whether a merged index goes back far enough, worked out from scratch
+    fn reaches_back(feed: &Feed, index: &FeedIndex<'_>) -> bool {
+        goes_back(feed, index.len(), index.last().map(|ep| ep.when))
+    }
+
+    fn planned<'a>(plan: &FetchPlan<'a>) -> Vec<&'a str> {
+        plan.episodes.iter().map(|ep| ep.id).collect()
+    }
+
+    #[test]
+    fn new_feed_fetches_its_backlog() {
+        // searching the newest-first list as if it were oldest-first took all five
+        let link = Url::parse("https://example.com/ep.mp3").unwrap();
+        let index = index(&link, 5);
+        let plan = plan_fetch(&feed(2, None), &index);
+        assert_eq!(planned(&plan), ["ep5", "ep4"]);
+        assert_eq!(plan.set_fetch_since, Some(day(4)));
+    }
+
+    #[test]
+    fn mature_feed_fetches_since_its_date() {
+        let link = Url::parse("https://example.com/ep.mp3").unwrap();
+        let index = index(&link, 9);
+        let plan = plan_fetch(&feed(1, Some(day(6))), &index);
+        assert_eq!(planned(&plan), ["ep9", "ep8", "ep7", "ep6"]);
+        assert_eq!(plan.set_fetch_since, None);
+    }
+
+    #[test]
+    fn backlog_longer_than_feed_takes_everything() {
+        let link = Url::parse("https://example.com/ep.mp3").unwrap();
+        let index = index(&link, 3);
+        let plan = plan_fetch(&feed(10, None), &index);
+        assert_eq!(planned(&plan), ["ep3", "ep2", "ep1"]);
+    }
+
+    #[test]
+    fn archive_reach_follows_merged_pages() {
+        let link = Url::parse("https://example.com/ep.mp3").unwrap();
+        let newest: FeedIndex<'_> = index(&link, 9).iter().take(3).cloned().collect();
+        // the second page repeats the end of the first, as archives that overlap do
+        let older: FeedIndex<'_> = index(&link, 7).iter().take(4).cloned().collect();
+        let oldest: FeedIndex<'_> = index(&link, 3).iter().cloned().collect();
+
+        for feed in [feed(5, None), feed(8, None), feed(1, Some(day(5))), feed(1, Some(day(2)))] {
+            let mut reach = ArchiveReach::default();
+            let mut pages = Vec::new();
+            for page in [&newest, &older, &oldest] {
+                reach.add(page);
+                pages.push(page.clone());
+                let merged = FeedIndex::merge(pages.iter().cloned());
+                assert_eq!(reach.reaches_back(&feed), reaches_back(&feed, &merged),
+                    "backlog {} after {} pages", feed.backlog, pages.len());
+            }
+        }
+
+        assert!(!ArchiveReach::default().reaches_back(&feed(1, None)));
+    }
+}
@@ -0,0 +1,282 @@
+
+use {
+    crate::{Anyhow, ExitStatus, Options, options::OutputFormat},
+    podchamp::{models::Feed, EpisodeMeta, Overrides},
+    anyhow::bail,
+    chrono::prelude::*,
+    serde::Serialize,
+    tokio::io::AsyncWriteExt as _,
+    url::Url,
+};
+
+/// How a feed's episodes are to be downloaded, taking into account any overrides from its tags
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FeedSettings<'a> {
+    pub downloader:  &'a str,
+    pub date_format: &'a str,
+}
+
+impl<'a> FeedSettings<'a> {
+    pub fn new(opts: &'a Options, overrides: &'a Overrides) -> Self {
+        FeedSettings {
+            downloader:  overrides.downloader.as_deref().unwrap_or(&opts.downloader),
+            date_format: overrides.date_format.as_deref().unwrap_or(&opts.date_format),
+        }
+    }
+}
+
+/// Where a downloader's or hook's standard output should go: to ours, unless that's carrying JSON
+/// events, in which case to standard error, so that whatever reads the events isn't thrown by it
+pub(crate) fn child_stdout(opts: &Options) -> std::process::Stdio {
+    if opts.output_format() == OutputFormat::Human {
+        return std::process::Stdio::inherit();
+    }
+
+    #[cfg(unix)]
+    let stderr = {
+        use std::os::fd::AsFd as _;
+        std::io::stderr().as_fd().try_clone_to_owned().map(Into::into)
+    };
+    #[cfg(windows)]
+    let stderr = {
+        use std::os::windows::io::AsHandle as _;
+        std::io::stderr().as_handle().try_clone_to_owned().map(Into::into)
+    };
+    stderr.unwrap_or_else(|_| std::process::Stdio::null())
+}
+
+/// Builds a command to be run for a particular episode, with its link as the lone argument and
+/// episode metadata in its environment
+///
+/// `replaced` marks a download of an episode whose enclosure has changed since it was last
+/// downloaded.
+pub(crate)
+fn episode_command(
+    program:  &str,
+    settings: FeedSettings<'_>,
+    feed:     &Feed<'_>,
+    meta:     &EpisodeMeta<'_>,
+    link:     &Url,
+    date:     &DateTime<Utc>,
+    replaced: bool)
+    -> std::process::Command
+{
+    let mut command = std::process::Command::new(program);
+    command.arg(link.as_str());
+
+    let date = date.format(settings.date_format)
+        .to_string();
+
+    let envs = [
+        ("PODCHAMP_FEED",        Some(&feed.name[..])),
+        ("PODCHAMP_DATE",        Some(&date[..])),
+        ("PODCHAMP_TITLE",       meta.title),
+        ("PODCHAMP_REPLACED",    replaced.then_some("1")),
+    //  ("PODCHAMP_AUTHOR",      item.author()),
+    //  ("PODCHAMP_DESCRIPTION", item.summary),
+    ];
+
+    for (var, value) in envs.iter() {
+        if let Some(value) = value {
+            command.env(var, value);
+        }
+    }
+
+    command
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum DownloadError {
+    #[error("launching download command: {0}")]
+    Launch(#[from] std::io::Error),
+    #[error("download command failed with code {0:?}")]
+    Failed(Option<i32>),
+    #[error("download cancelled")]
+    Cancelled,
+    #[error("opening download log: {0}")]
+    Log(std::io::Error),
+}
+
+/// The outcome of an episode download, as reported to the episode hooks
+pub(crate) type DownloadResult = Result<(), DownloadError>;
+
+/// Runs `on_episode_done` or `on_episode_failed`, as appropriate, for a finished download
+#[allow(clippy::too_many_arguments)]
+pub(crate)
+async fn run_episode_hook(
+    opts:     &Options,
+    settings: FeedSettings<'_>,
+    feed:     &Feed<'_>,
+    meta:     &EpisodeMeta<'_>,
+    link:     &Url,
+    date:     &DateTime<Utc>,
+    replaced: bool,
+    result:   &DownloadResult)
+    -> Anyhow<()>
+{
+    let program = match result {
+        Ok(())  => opts.on_episode_done.as_deref(),
+        Err(_)  => opts.on_episode_failed.as_deref(),
+    };
+
+    let program = match program {
+        Some(program) => program,
+        None          => return Ok(()),
+    };
+
+    let mut command = episode_command(program, settings, feed, meta, link, date, replaced);
+    match result {
+        Ok(()) => {
+            command.env("PODCHAMP_RESULT", "done");
+            command.env("PODCHAMP_EXIT_CODE", "0");
+        }
+
+        Err(e) => {
+            command.env("PODCHAMP_RESULT", "failed");
+            command.env("PODCHAMP_ERROR", e.to_string());
+            if let DownloadError::Failed(Some(code)) = e {
+                command.env("PODCHAMP_EXIT_CODE", code.to_string());
+            }
+        }
+    }
+
+    let status = tokio::process::Command::from(command)
+        .stdout(child_stdout(opts))
+        .status().await?;
+    if !status.success() {
+        bail!("Episode hook failed with code {:?}", status.code());
+    }
+
+    Ok(())
+}
+
+/// A downloaded or failed episode, as reported to `on_fetch_complete`
+#[derive(Debug, Serialize)]
+pub(crate) struct EpisodeSummary {
+    pub feed:  String,
+    pub title: Option<String>,
+    pub date:  DateTime<Utc>,
+    pub link:  String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A feed that could not be fetched at all, as reported to `on_fetch_complete`
+#[derive(Debug, Serialize)]
+pub(crate) struct FeedFailure {
+    pub feed:   String,
+    pub error:  String,
+    /// The exit status the failure calls for, if it's one with a status of its own, like a
+    /// database error
+    #[serde(skip)]
+    pub status: Option<ExitStatus>,
+}
+
+/// Summary of a whole fetch run, passed as JSON on the standard input of `on_fetch_complete`
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct FetchSummary {
+    pub downloaded:    Vec<EpisodeSummary>,
+    pub failed:        Vec<EpisodeSummary>,
+    pub failed_feeds:  Vec<FeedFailure>,
+    #[serde(skip)]
+    pub n_feeds:       usize,
+}
+
+impl FetchSummary {
+    pub fn record_episode(&mut self,
+        feed:   &Feed<'_>,
+        meta:   &EpisodeMeta<'_>,
+        link:   &Url,
+        date:   &DateTime<Utc>,
+        result: &DownloadResult)
+    {
+        let summary = EpisodeSummary {
+            feed:  feed.name.to_string(),
+            title: meta.title.map(Into::into),
+            date:  *date,
+            link:  link.as_str().into(),
+            error: result.as_ref().err().map(ToString::to_string),
+        };
+
+        match result {
+            Ok(())  => self.downloaded.push(summary),
+            Err(_)  => self.failed.push(summary),
+        }
+    }
+
+    /// Names of feeds that failed outright, or whose every attempted download failed
+    pub fn failed_feed_names(&self) -> std::collections::BTreeSet<&str> {
+        let any_downloaded = |feed: &str| self.downloaded.iter().any(|ep| ep.feed == feed);
+        self.failed_feeds.iter().map(|failure| &failure.feed[..])
+            .chain(self.failed.iter()
+                .map(|ep| &ep.feed[..])
+                .filter(|feed| !any_downloaded(feed)))
+            .collect()
+    }
+
+    pub fn exit_status(&self) -> ExitStatus {
+        // these say more about what went wrong than how many feeds it went wrong for
+        for status in [ExitStatus::DatabaseError, ExitStatus::LockHeld] {
+            if self.failed_feeds.iter().any(|failure| failure.status == Some(status)) {
+                return status;
+            }
+        }
+
+        if self.failed.is_empty() && self.failed_feeds.is_empty() {
+            ExitStatus::Success
+        }
+        else if self.failed_feed_names().len() >= self.n_feeds {
+            ExitStatus::TotalFailure
+        }
+        else {
+            ExitStatus::PartialFailure
+        }
+    }
+
+    pub fn record_feed_failure(&mut self, feed: &str, error: &anyhow::Error) {
+        let status = match ExitStatus::for_error(error) {
+            ExitStatus::Error => None,
+            status            => Some(status),
+        };
+        self.failed_feeds.push(FeedFailure {
+            feed:   feed.into(),
+            error:  error.to_string(),
+            status,
+        });
+    }
+}
+
+/// Runs `on_fetch_complete`, if configured, with the run's summary on its standard input
+pub(crate)
+async fn run_fetch_complete_hook(opts: &Options, summary: &FetchSummary) -> Anyhow<()> {
+    let program = match &opts.on_fetch_complete {
+        Some(program) => program,
+        None          => return Ok(()),
+    };
+
+    let json = serde_json::to_vec(summary)?;
+
+    let mut child = tokio::process::Command::new(program)
+        .env("PODCHAMP_DOWNLOADED", summary.downloaded.len().to_string())
+        .env("PODCHAMP_FAILED", (summary.failed.len() + summary.failed_feeds.len()).to_string())
+        .stdin(std::process::Stdio::piped())
+        .stdout(child_stdout(opts))
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // a hook that has no use for the summary may well exit without reading it
+        match stdin.write_all(&json).await {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => { }
+        }
+        // so that the hook sees the end of its input, rather than waiting on us for more
+        drop(stdin);
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        bail!("Fetch-complete hook failed with code {:?}", status.code());
+    }
+
+    Ok(())
+}
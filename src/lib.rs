@@ -6,5 +6,13 @@ pub mod database;
 pub use database::*;
 
 pub mod fetch;
-pub use fetch::{EpisodeMeta, Episode, FeedIndex, plan_fetch};
+pub use fetch::{ArchiveReach, EpisodeMeta, Episode, FeedIndex, plan_fetch};
+
+pub mod discovery;
+
+pub mod schedule;
+
+pub mod search;
+
+pub mod websub;
 
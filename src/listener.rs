@@ -0,0 +1,311 @@
+
+use {
+    crate::{
+        Anyhow,
+        report::{Event, Reporter},
+    },
+    podchamp::{Database, websub},
+    chrono::prelude::*,
+    hyper::{Body, Method, Request, Response, StatusCode},
+    std::{
+        collections::HashMap,
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    tokio::sync::mpsc,
+    url::Url,
+};
+
+/// How long a lease to ask hubs for
+const REQUESTED_LEASE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How long to wait for a hub to verify a subscription before asking again
+const RETRY_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// Something a hub has told us
+#[derive(Debug)]
+pub(crate) enum Notice {
+    /// The hub has confirmed a subscription
+    Verified { feed: String, lease: Duration },
+    /// The hub has refused or cancelled a subscription
+    Denied { feed: String, reason: Option<String> },
+    /// The hub says a feed has been updated
+    Pushed { feed: String },
+}
+
+/// What the listener needs to know about a subscription
+#[derive(Debug, Clone)]
+struct Registration {
+    feed:   String,
+    topic:  String,
+    secret: String,
+}
+
+struct ListenerState {
+    /// Subscriptions, by callback ID
+    registrations: Mutex<HashMap<String, Registration>>,
+    notices:       mpsc::UnboundedSender<Notice>,
+}
+
+/// Keeps WebSub subscriptions to the hubs our feeds advertise, and listens for the hubs' callbacks
+pub(crate) struct WebSub {
+    callback_base: Url,
+    state:         Arc<ListenerState>,
+    notices:       mpsc::UnboundedReceiver<Notice>,
+    /// When each unverified subscription request was sent
+    pending:       HashMap<String, Instant>,
+    client:        reqwest::Client,
+}
+
+impl WebSub {
+    /// Starts listening on `listen`, for callbacks addressed to URLs under `callback_base`
+    ///
+    /// Subscriptions made by an earlier run are answered for from the start, since their hubs may
+    /// call back before the daemon gets around to [`WebSub::maintain`].
+    pub fn start(listen: SocketAddr, callback_base: Url, db: &Database) -> Anyhow<Self> {
+        let (notices, notices_rx) = mpsc::unbounded_channel();
+        let state = Arc::new(ListenerState {
+            registrations: Mutex::new(known_registrations(db)?),
+            notices,
+        });
+
+        let make_service = hyper::service::make_service_fn({
+            let state = state.clone();
+            move |_| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, Infallible>(hyper::service::service_fn(move |req| {
+                        handle(state.clone(), req)
+                    }))
+                }
+            }
+        });
+
+        let server = hyper::Server::try_bind(&listen)?.serve(make_service);
+        tokio::spawn(server);
+
+        Ok(WebSub {
+            callback_base,
+            state,
+            notices: notices_rx,
+            pending: HashMap::new(),
+            client:  reqwest::Client::new(),
+        })
+    }
+
+    /// Waits for a hub to tell us something
+    pub async fn next_notice(&mut self) -> Notice {
+        match self.notices.recv().await {
+            Some(notice) => notice,
+            // the listener holds a sender for as long as it runs, and it runs forever
+            None => std::future::pending().await,
+        }
+    }
+
+    fn callback_url(&self, callback_id: &str) -> String {
+        format!("{}/{}", self.callback_base.as_str().trim_end_matches('/'), callback_id)
+    }
+
+    /// Brings the listener up to date with the database, and subscribes to any hubs that need it
+    pub async fn maintain(&mut self, db: &mut Database, reporter: &Reporter) -> Anyhow<()> {
+        let now = Utc::now();
+        let mut registrations = HashMap::new();
+        let mut due = Vec::new();
+
+        for (feed, sub) in db.get_websub_subscriptions()? {
+            let (callback_id, secret) = match (sub.callback_id, sub.secret) {
+                (Some(callback_id), Some(secret)) => (callback_id.into_owned(), secret.into_owned()),
+                _ => {
+                    let callback_id = random_token()?;
+                    let secret = random_token()?;
+                    db.set_websub_callback(&feed, &callback_id, &secret)?;
+                    (callback_id, secret)
+                }
+            };
+
+            let renew = sub.renew_at
+                .map(|naive| DateTime::<Utc>::from_utc(naive, Utc))
+                .is_none_or(|renew_at| renew_at <= now);
+            let asked_lately = self.pending.get(&feed)
+                .is_some_and(|asked| asked.elapsed() < RETRY_AFTER);
+            if renew && !asked_lately {
+                due.push((feed.clone(), sub.hub.to_string(), callback_id.clone()));
+            }
+
+            registrations.insert(callback_id, Registration {
+                feed,
+                topic:  sub.topic.into_owned(),
+                secret,
+            });
+        }
+
+        // hubs may verify before they even answer the subscription request, so the listener has
+        // to know about the subscriptions first
+        *self.state.registrations.lock().unwrap() = registrations.clone();
+
+        for (feed, hub, callback_id) in due {
+            let registration = &registrations[&callback_id];
+            let lease = REQUESTED_LEASE.as_secs().to_string();
+            let form = [
+                ("hub.mode",          "subscribe"),
+                ("hub.callback",      &self.callback_url(&callback_id)),
+                ("hub.topic",         &registration.topic),
+                ("hub.secret",        &registration.secret),
+                ("hub.lease_seconds", &lease),
+            ];
+
+            self.pending.insert(feed.clone(), Instant::now());
+            let message = match self.client.post(&hub).form(&form).send().await {
+                Ok(resp) if resp.status().is_success()
+                    => format!("Subscribing to {} via {}", feed, hub),
+                Ok(resp)
+                    => format!("Hub {} refused subscription to {}: {}", hub, feed, resp.status()),
+                Err(e)
+                    => format!("Error subscribing to {} via {}: {}", feed, hub, e),
+            };
+            reporter.emit(Event::Note{message});
+        }
+
+        Ok(())
+    }
+
+    /// Acts on a notice from a hub, returning the name of a feed that should be fetched, if any
+    pub fn handle(&mut self, db: &mut Database, notice: Notice, reporter: &Reporter)
+        -> Anyhow<Option<String>>
+    {
+        match notice {
+            Notice::Verified{feed, lease} => {
+                self.pending.remove(&feed);
+                let now = Utc::now();
+                let lease = chrono::Duration::from_std(lease)?;
+                db.set_websub_lease(&feed, Some(now + lease), Some(now + lease * 3 / 4))?;
+                let message = format!("Subscribed to {} for {}s", feed, lease.num_seconds());
+                reporter.emit(Event::Note{message});
+                Ok(None)
+            }
+
+            Notice::Denied{feed, reason} => {
+                // leave it a while before trying again
+                self.pending.insert(feed.clone(), Instant::now());
+                db.set_websub_lease(&feed, None, None)?;
+                let reason = reason.unwrap_or_else(|| "no reason given".into());
+                let message = format!("Hub denied subscription to {}: {}", feed, reason);
+                reporter.emit(Event::Note{message});
+                Ok(None)
+            }
+
+            Notice::Pushed{feed} => {
+                reporter.emit(Event::Note{message: format!("Hub reports {} updated", feed)});
+                Ok(Some(feed))
+            }
+        }
+    }
+}
+
+/// The subscriptions that already have callbacks, by callback ID
+fn known_registrations(db: &Database) -> Anyhow<HashMap<String, Registration>> {
+    Ok(db.get_websub_subscriptions()?.into_iter()
+        .filter_map(|(feed, sub)| {
+            let registration = Registration {
+                feed,
+                topic:  sub.topic.into_owned(),
+                secret: sub.secret?.into_owned(),
+            };
+            Some((sub.callback_id?.into_owned(), registration))
+        })
+        .collect())
+}
+
+fn random_token() -> Anyhow<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(websub::encode_hex(&bytes))
+}
+
+fn respond(status: StatusCode, body: impl Into<Body>) -> Result<Response<Body>, Infallible> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+async fn handle(state: Arc<ListenerState>, req: Request<Body>)
+    -> Result<Response<Body>, Infallible>
+{
+    let callback_id = req.uri().path().rsplit('/').next().unwrap_or_default().to_owned();
+    let registration = state.registrations.lock().unwrap().get(&callback_id).cloned();
+    let registration = match registration {
+        Some(registration) => registration,
+        // tell the hub this subscription is gone, so it stops bothering us
+        None => return respond(StatusCode::GONE, Body::empty()),
+    };
+
+    match *req.method() {
+        Method::GET  => handle_verification(&state, registration, &req),
+        Method::POST => handle_content(&state, registration, req).await,
+        _            => respond(StatusCode::METHOD_NOT_ALLOWED, Body::empty()),
+    }
+}
+
+/// Answers a hub's verification of intent, or its notice that a subscription was denied
+fn handle_verification(state: &ListenerState, registration: Registration, req: &Request<Body>)
+    -> Result<Response<Body>, Infallible>
+{
+    let params: HashMap<String, String> = url::form_urlencoded::parse(
+            req.uri().query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect();
+    let param = |name: &str| params.get(name).map(String::as_str);
+
+    let feed = registration.feed;
+    match param("hub.mode") {
+        Some("subscribe") if param("hub.topic") == Some(&registration.topic[..]) => {
+            let challenge = match param("hub.challenge") {
+                Some(challenge) => challenge.to_owned(),
+                None => return respond(StatusCode::BAD_REQUEST, Body::empty()),
+            };
+
+            let lease = param("hub.lease_seconds")
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(REQUESTED_LEASE);
+            let _ = state.notices.send(Notice::Verified{feed, lease});
+            respond(StatusCode::OK, challenge)
+        }
+
+        Some("denied") => {
+            let reason = param("hub.reason").map(String::from);
+            let _ = state.notices.send(Notice::Denied{feed, reason});
+            respond(StatusCode::OK, Body::empty())
+        }
+
+        // we never unsubscribe, and we don't confirm subscriptions to topics we didn't ask for
+        _ => respond(StatusCode::NOT_FOUND, Body::empty()),
+    }
+}
+
+/// Accepts a content distribution request, if it's properly signed
+async fn handle_content(state: &ListenerState, registration: Registration, req: Request<Body>)
+    -> Result<Response<Body>, Infallible>
+{
+    let signature = req.headers().get("x-hub-signature")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(_)   => return respond(StatusCode::BAD_REQUEST, Body::empty()),
+    };
+
+    // unsigned or badly-signed notifications must be acknowledged all the same, but ignored
+    let authentic = signature.is_some_and(|signature| {
+        websub::signature_valid(registration.secret.as_bytes(), &signature, &body)
+    });
+
+    if authentic {
+        let _ = state.notices.send(Notice::Pushed{feed: registration.feed});
+    }
+
+    respond(StatusCode::ACCEPTED, Body::empty())
+}
@@ -0,0 +1,180 @@
+
+use {
+    crate::Anyhow,
+    std::{
+        collections::HashSet,
+        fs::File,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// How often to check the lock while waiting for another instance
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum InstanceLockError {
+    #[error("another instance of podchamp is working on {0} (lock file {1} is held)")]
+    Held(String, PathBuf),
+}
+
+/// How long to wait for another instance to finish before giving up
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Wait {
+    No,
+    For(Duration),
+    Forever,
+}
+
+impl From<Option<Option<u64>>> for Wait {
+    fn from(opt: Option<Option<u64>>) -> Self {
+        match opt {
+            None             => Wait::No,
+            Some(None)       => Wait::Forever,
+            Some(Some(secs)) => Wait::For(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// The locks held by this process
+///
+/// Each lock is an exclusive lock on a file in the runtime directory, which holds the PID of its
+/// owner for the curious. Feeds are locked individually, so that instances working on different
+/// feeds don't get in each other's way. The system releases a lock when its owner exits, however
+/// it exits, so there are never stale locks to clean up.
+#[derive(Clone)]
+pub(crate) struct Locks {
+    dir:  PathBuf,
+    held: Arc<Mutex<Vec<(PathBuf, File)>>>,
+}
+
+impl Locks {
+    pub fn new(rt_path: &Path) -> Self {
+        Locks {
+            dir:  rt_path.join("locks"),
+            held: Default::default(),
+        }
+    }
+
+    fn lock_path(&self, feed: &str) -> PathBuf {
+        self.dir.join(format!("{}.lock", escape_file_name(feed)))
+    }
+
+    /// Locks a feed, so that no other instance will modify or fetch it until this one is done
+    pub async fn lock_feed(&self, feed: &str, wait: Wait) -> Anyhow<()> {
+        use std::io::Write as _;
+        std::fs::create_dir_all(&self.dir)?;
+
+        let lock_path = self.lock_path(feed);
+        let deadline = match wait {
+            Wait::No         => Some(Instant::now()),
+            Wait::For(limit) => Some(Instant::now() + limit),
+            Wait::Forever    => None,
+        };
+
+        // the file is never removed, since another instance may be waiting on it
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+
+        let mut announced = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(std::fs::TryLockError::WouldBlock) => { }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(InstanceLockError::Held(feed.into(), lock_path).into());
+            }
+
+            if !announced {
+                eprintln!("Waiting for another instance of podchamp to finish with {}", feed);
+                announced = true;
+            }
+
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+
+        let result = file.set_len(0).and_then(|()| write!(file, "{}", std::process::id()));
+        self.held.lock().unwrap().push((lock_path, file));
+        result?;
+
+        Ok(())
+    }
+
+    /// Releases the lock on a feed, if this instance holds it
+    pub fn unlock_feed(&self, feed: &str) {
+        let lock_path = self.lock_path(feed);
+        if let Ok(mut held) = self.held.lock() {
+            if let Some(at) = held.iter().position(|(path, _)| path == &lock_path) {
+                // closing the file releases the lock
+                held.swap_remove(at);
+            }
+        }
+    }
+
+    /// Releases every lock held
+    pub fn done(&self) {
+        if let Ok(mut held) = self.held.lock() {
+            held.clear();
+        }
+    }
+}
+
+impl Drop for Locks {
+    fn drop(&mut self) {
+        self.done();
+    }
+}
+
+/// Locks on a set of feeds, which are released when it's dropped if they haven't been already
+pub(crate) struct FeedLocks<'l> {
+    locks: &'l Locks,
+    feeds: HashSet<String>,
+}
+
+impl<'l> FeedLocks<'l> {
+    pub fn new(locks: &'l Locks) -> Self {
+        FeedLocks{locks, feeds: HashSet::new()}
+    }
+
+    pub async fn lock(&mut self, feed: &str, wait: Wait) -> Anyhow<()> {
+        self.locks.lock_feed(feed, wait).await?;
+        self.feeds.insert(feed.into());
+        Ok(())
+    }
+
+    pub fn contains(&self, feed: &str) -> bool {
+        self.feeds.contains(feed)
+    }
+
+    pub fn unlock(&mut self, feed: &str) {
+        if self.feeds.remove(feed) {
+            self.locks.unlock_feed(feed);
+        }
+    }
+}
+
+impl Drop for FeedLocks<'_> {
+    fn drop(&mut self) {
+        for feed in self.feeds.drain() {
+            self.locks.unlock_feed(&feed);
+        }
+    }
+}
+
+/// Makes a feed name safe to use as a file name
+fn escape_file_name(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
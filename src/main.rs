@@ -1,60 +1,114 @@
 
 #![forbid(unsafe_code)]
 
+mod command_add;
+mod command_ctl;
+mod command_daemon;
 mod command_fetch;
+mod command_log;
+mod command_ls;
+mod command_search;
+mod command_status;
+mod command_tags;
+mod control;
+mod directory;
+mod downloads;
+mod hooks;
+mod listener;
+mod lock;
 mod options;
+mod report;
+mod table;
 
 use {
-    crate::{command_fetch::fetch, options::*},
+    crate::{
+        command_add::{add, new_link},
+        command_ctl::{ctl, hand_off_fetch},
+        command_daemon::daemon,
+        command_fetch::{fetch, FetchScope, ReadArchive},
+        command_log::log,
+        command_ls::ls,
+        command_search::{search, SearchScope},
+        command_status::status,
+        command_tags::tags,
+        control::{ControlClient, FetchRequest},
+        lock::{InstanceLockError, Locks, Wait},
+        options::*,
+    },
+    anyhow::Context as _,
     chrono::prelude::*,
 };
 
 pub(crate) use anyhow::Result as Anyhow;
 
-#[derive(Clone)]
-struct SingleInstance(std::sync::Arc<std::sync::Mutex<Option<std::path::PathBuf>>>);
-
-impl SingleInstance {
-    fn new(rt_path: &std::path::Path) -> Anyhow<Self> {
-        std::fs::create_dir_all(rt_path)?;
-
-        let lockdir_path = rt_path.join("podchamp.lock.d");
-        std::fs::create_dir(&lockdir_path)?;
-
-        let pid = format!("{}", std::process::id());
-        std::fs::write(lockdir_path.join("pid"), &pid)?;
-
-        let utx = std::sync::Mutex::new(Some(lockdir_path));
-        Ok(SingleInstance(std::sync::Arc::new(utx)))
-    }
+/// The process's exit status, so that scripts and service managers can tell how things went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitStatus {
+    /// Everything worked
+    Success        = 0,
+    /// Something went wrong that isn't covered below
+    Error          = 1,
+    /// Some feeds or episodes failed to fetch, but not all of them
+    PartialFailure = 3,
+    /// Every feed failed to fetch
+    TotalFailure   = 4,
+    /// Another instance of podchamp is working on the same feed
+    LockHeld       = 5,
+    /// The database could not be opened or updated
+    DatabaseError  = 6,
+    /// The fetch was handed to a running daemon, so how it goes isn't known yet
+    Queued         = 7,
+}
 
-    fn done(&self) {
-        if let Ok(mut lock) = self.0.lock() {
-            if let Some(path) = lock.take() {
-                let _ = std::fs::remove_dir_all(&path);
-            }
+impl ExitStatus {
+    fn for_error(error: &anyhow::Error) -> Self {
+        if error.downcast_ref::<InstanceLockError>().is_some() {
+            ExitStatus::LockHeld
+        }
+        else if error.chain().any(podchamp::is_database_error) {
+            ExitStatus::DatabaseError
+        }
+        else {
+            ExitStatus::Error
         }
     }
 }
 
-impl Drop for SingleInstance {
-    fn drop(&mut self) {
-        self.done();
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        (status as u8).into()
     }
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Anyhow<()> {
+async fn main() -> std::process::ExitCode {
+    match run().await {
+        Ok(status) => status.into(),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitStatus::for_error(&e).into()
+        }
+    }
+}
+
+async fn run() -> Anyhow<ExitStatus> {
     let now = Utc::now();
 
     let opts = options::Options::load();
 
-    let instance = SingleInstance::new(&opts.runtime_dir_path)?;
+    // read-only commands don't lock anything; the rest lock the feeds they touch as they go. a
+    // running daemon only holds a feed's lock while it's fetching it, so it's worth waiting for
+    let locks = Locks::new(&opts.runtime_dir_path);
+    let daemon_running = ControlClient::connect(&opts.runtime_dir_path).await;
+    let wait = match opts.wait {
+        None if daemon_running.is_some() => Wait::Forever,
+        wait => wait.into(),
+    };
     std::panic::set_hook({
         let hook = std::panic::take_hook();
-        let instance = instance.clone();
+        let locks = locks.clone();
         Box::new(move |info| {
-            instance.done();
+            locks.done();
             (hook)(info)
         })
     });
@@ -62,29 +116,75 @@ async fn main() -> Anyhow<()> {
     let mut db = podchamp::Database::open(&opts.database_path)?;
 
     match &opts.command {
-        Command::Add{name, link, backlog} => {
+        Command::Add{name, link, backlog, no_check, force} => {
             let backlog = backlog.or(std::num::NonZeroU32::new(1)).unwrap();
-            db.add_feed(name, link, backlog)?;
-            eprintln!("Added {}", name);
+            let (name, link) = match link {
+                Some(link) => (Some(&name[..]), link.clone()),
+                None => {
+                    let link = name.parse().with_context(|| format!("{} isn't a link", name))?;
+                    (None, link)
+                }
+            };
+            add(&mut db, &opts, &locks, wait, name, &link, backlog, !no_check, *force, now).await?;
         }
 
         Command::Rm{name} => {
+            locks.lock_feed(name, wait).await?;
             db.remove_feed(name)?;
         }
 
-        Command::Ls => {
-            let results = db.get_feeds(podchamp::GetFeeds::All)?;
-            if results.is_empty() { eprintln!("No feeds. You can add one with `podchamp add`."); }
-            for feed in results {
-                // TODO tabulate
-                println!("{:16} {}", feed.name, feed.uri);
-            }
+        Command::Ls{columns, tags, long, sort, reverse, format} => {
+            let columns = match long {
+                true  => &command_ls::ALL_COLUMNS[..],
+                false => &columns[..],
+            };
+            ls(&db, columns, tags, *sort, *reverse, *format)?;
+        }
+
+        Command::Log{feed, since, failed, runs, long} => {
+            let filter = podchamp::HistoryFilter {
+                feed:        feed.as_deref(),
+                since:       since.map(|since| since.0),
+                failed_only: *failed,
+            };
+            log(&db, filter, *runs, *long)?;
+        }
+
+        Command::Search{query, feeds, tags, limit, long, download} => {
+            let scope = SearchScope {
+                feeds,
+                tags,
+                limit:    *limit,
+                long:     *long,
+                download: download.map(|n| n.map_or(1, std::num::NonZeroUsize::get)),
+            };
+            return search(&mut db, &opts, &locks, wait, &query.join(" "), scope, daemon_running)
+                .await;
+        }
+
+        Command::Status{failures, quiet} => {
+            status(&db, now, *failures, *quiet)?;
         }
 
         Command::Mod{feed, how} => {
+            locks.lock_feed(feed, wait).await?;
             match how {
+                Modification::Name{name} if name == feed => {
+                    if db.get_feeds(podchamp::GetFeeds::One(feed))?.is_empty() {
+                        anyhow::bail!("no feed named {}", feed);
+                    }
+                    eprintln!("{} is already called {}", feed, name);
+                }
+
+                Modification::Name{name} => {
+                    locks.lock_feed(name, wait).await?;
+                    db.rename_feed(feed, name)?;
+                    eprintln!("Renamed {} to {}", feed, name);
+                }
+
                 Modification::Link{link} => {
-                    db.set_link(feed, link)?;
+                    let link = new_link(&opts, link, now).await?;
+                    db.set_link(feed, &link)?;
                     eprintln!("Changed {} feed link to {}", feed, link);
                 }
 
@@ -92,17 +192,158 @@ async fn main() -> Anyhow<()> {
                     db.set_backlog(feed, *n)?;
                     eprintln!("Changed {} backlog to {}", feed, n);
                 }
+
+                Modification::Interval{every: Some(every)} => {
+                    db.set_poll_interval(feed, Some(every.0))?;
+                    eprintln!("Changed {} polling interval to {}", feed, every);
+                }
+
+                Modification::Interval{every: None} => {
+                    db.set_poll_interval(feed, None)?;
+                    eprintln!("Changed {} polling interval to the default", feed);
+                }
+
+                Modification::Dedupe{setting: Some(setting)} => {
+                    db.set_dedupe(feed, Some(*setting == Switch::On))?;
+                    let setting = match setting { Switch::On => "on", Switch::Off => "off" };
+                    eprintln!("Turned {} de-duplication {}", feed, setting);
+                }
+
+                Modification::Dedupe{setting: None} => {
+                    db.set_dedupe(feed, None)?;
+                    eprintln!("Changed {} de-duplication to the default", feed);
+                }
+
+                Modification::Archive{setting} => {
+                    db.set_archive(feed, *setting == Switch::On)?;
+                    let setting = match setting { Switch::On => "on", Switch::Off => "off" };
+                    eprintln!("Turned {} archive reading {}", feed, setting);
+                }
+            }
+        }
+
+        Command::Pause{feed} => {
+            locks.lock_feed(feed, wait).await?;
+            db.set_enabled(feed, false)?;
+            eprintln!("Paused {}", feed);
+        }
+
+        Command::Resume{feed} => {
+            locks.lock_feed(feed, wait).await?;
+            db.set_enabled(feed, true)?;
+            eprintln!("Resumed {}", feed);
+        }
+
+        Command::Tag{feed, tags} => {
+            locks.lock_feed(feed, wait).await?;
+            db.add_tags(feed, tags)?;
+            eprintln!("Tagged {} with {}", feed, tags.join(", "));
+        }
+
+        Command::Untag{feed, tags} => {
+            locks.lock_feed(feed, wait).await?;
+            db.remove_tags(feed, tags)?;
+            eprintln!("Took {} off {}", tags.join(", "), feed);
+        }
+
+        Command::Tags => {
+            tags(&db)?;
+        }
+
+        Command::ModTag{tag, how} => match how {
+            TagModification::Downloader{command} => {
+                db.set_tag_downloader(tag, command.as_deref())?;
+                match command {
+                    Some(command) => eprintln!("Changed {} downloader to {}", tag, command),
+                    None          => eprintln!("Changed {} downloader to the default", tag),
+                }
+            }
+
+            TagModification::DateFormat{format} => {
+                db.set_tag_date_format(tag, format.as_deref())?;
+                match format {
+                    Some(format) => eprintln!("Changed {} date format to {}", tag, format),
+                    None         => eprintln!("Changed {} date format to the default", tag),
+                }
             }
         }
 
         Command::Reset{feed} => {
-            db.reset_register(&feed)?;
+            locks.lock_feed(feed, wait).await?;
+            db.reset_register(feed)?;
             eprintln!("Progress reset for {}", feed);
         }
 
-        Command::Fetch{feed} => fetch(&mut db, feed.as_deref(), &opts, now).await?,
+        Command::Daemon{interval, output, log_file, websub_listen, websub_callback} => {
+            let sink = match log_file {
+                Some(path) => report::Sink::open_log(path)?,
+                None       => report::Sink::Terminal,
+            };
+            let reporter = report::Reporter::for_log(*output, sink);
+            let websub = match (websub_listen, websub_callback) {
+                (Some(listen), Some(callback)) => {
+                    Some(listener::WebSub::start(*listen, callback.clone(), &db)?)
+                }
+                _ => None,
+            };
+            daemon(&mut db, &opts, interval.0, &reporter, &locks, websub).await?;
+        }
+
+        Command::Fetch{feeds, tags, force, include_paused, archive, output} => {
+            let which = match (&feeds[..], &tags[..]) {
+                ([], [])     => podchamp::GetFeeds::All,
+                ([feed], []) => podchamp::GetFeeds::One(feed),
+                (names, tags) => podchamp::GetFeeds::Selected{names, tags},
+            };
+            let reporter = report::Reporter::new(*output);
+
+            // leave the fetching to the daemon, if there is one
+            if let Some(mut daemon) = daemon_running {
+                let found = db.get_feeds(which)?;
+                let missing = feeds.iter()
+                    .find(|name| !found.iter().any(|feed| feed.name == **name));
+                if let Some(name) = missing {
+                    anyhow::bail!("no feed named {}", name);
+                }
+
+                if found.is_empty() {
+                    match which {
+                        podchamp::GetFeeds::Selected{..} => eprintln!("No feeds have those tags."),
+                        _ => eprintln!("No feeds. You can add one with `podchamp add`."),
+                    }
+                }
+                else {
+                    let request = FetchRequest {
+                        force:          *force,
+                        include_paused: *include_paused,
+                        archive:        *archive,
+                    };
+                    hand_off_fetch(&mut daemon, feeds, tags, request, &reporter).await?;
+                    return Ok(ExitStatus::Queued);
+                }
+                return Ok(ExitStatus::Success);
+            }
+
+            let scope = FetchScope {
+                which,
+                force:          *force,
+                include_paused: *include_paused,
+                wait,
+                archive:        match archive {
+                    true  => ReadArchive::All,
+                    false => ReadArchive::AsSet,
+                },
+            };
+            let downloads = downloads::Downloads::default();
+            let summary = fetch(&mut db, scope, &opts, now, &reporter, &locks, &downloads).await?;
+            return Ok(summary.exit_status());
+        }
+
+        Command::Ctl{command} => {
+            ctl(&opts, command).await?;
+        }
     }
 
-    Ok(())
+    Ok(ExitStatus::Success)
 }
 
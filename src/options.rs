@@ -63,6 +63,81 @@ impl std::fmt::Display for RuntimeDirPath {
     }
 }
 
+/// A length of time, given as a number with an optional unit suffix: `s`, `m`, `h` or `d`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interval(pub std::time::Duration);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseIntervalError {
+    #[error("expected a number, optionally followed by s, m, h or d")]
+    Malformed,
+    #[error("interval must not be zero")]
+    Zero,
+}
+
+impl std::str::FromStr for Interval {
+    type Err = ParseIntervalError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number.parse().map_err(|_| ParseIntervalError::Malformed)?;
+        let scale = match unit {
+            "" | "s" => 1,
+            "m"      => 60,
+            "h"      => 60 * 60,
+            "d"      => 24 * 60 * 60,
+            _        => return Err(ParseIntervalError::Malformed),
+        };
+        if number == 0 {
+            return Err(ParseIntervalError::Zero);
+        }
+        Ok(Interval(std::time::Duration::from_secs(number * scale)))
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.as_secs();
+        for (scale, unit) in [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")] {
+            if secs.is_multiple_of(scale) {
+                return write!(f, "{}{}", secs / scale, unit);
+            }
+        }
+        write!(f, "{}s", secs)
+    }
+}
+
+/// A point in time: a date like `2026-10-19`, a time in RFC 3339 format, or an interval like `7d`,
+/// meaning that long ago
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Since(pub chrono::DateTime<chrono::Utc>);
+
+#[derive(Debug, thiserror::Error)]
+#[error("expected a date like 2026-10-19, an RFC 3339 time, or an interval like 7d")]
+pub struct ParseSinceError;
+
+impl std::str::FromStr for Since {
+    type Err = ParseSinceError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use chrono::prelude::*;
+        let s = s.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Since(time.with_timezone(&Utc)));
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%F") {
+            let midnight = Local.from_local_datetime(&date.and_hms(0, 0, 0)).earliest()
+                .ok_or(ParseSinceError)?;
+            return Ok(Since(midnight.with_timezone(&Utc)));
+        }
+
+        let Interval(ago) = s.parse().map_err(|_| ParseSinceError)?;
+        let ago = chrono::Duration::from_std(ago).map_err(|_| ParseSinceError)?;
+        Ok(Since(Utc::now() - ago))
+    }
+}
+
 #[derive(clap::Parser)]
 #[clap(about, author, version)]
 pub struct Options {
@@ -74,6 +149,12 @@ pub struct Options {
     #[clap(long, default_value_t, env = "PODCHAMP_RUNTIME_DIR")]
     pub runtime_dir_path: RuntimeDirPath,
 
+    /// Wait for another running instance to finish, rather than exiting
+    ///
+    /// If SECS is given, give up after that many seconds.
+    #[clap(long, value_name = "SECS", require_equals = true)]
+    pub wait: Option<Option<u64>>,
+
     /// Command to invoke when downloading episodes
     ///
     /// This command is invoked with the URI of the file to be downloaded as its lone argument.
@@ -88,6 +169,92 @@ pub struct Options {
     #[clap(long, default_value = "%F", env = "PODCHAMP_DATE_FORMAT")]
     pub date_format: String,
 
+    /// Command to invoke after an episode has been downloaded successfully
+    ///
+    /// This command receives the same argument and environment as the downloader, plus
+    /// `PODCHAMP_RESULT=done` and `PODCHAMP_EXIT_CODE`
+    #[clap(long, env = "PODCHAMP_ON_EPISODE_DONE")]
+    pub on_episode_done: Option<String>,
+
+    /// Command to invoke after an episode download has failed
+    ///
+    /// This command receives the same argument and environment as the downloader, plus
+    /// `PODCHAMP_RESULT=failed`, `PODCHAMP_ERROR`, and `PODCHAMP_EXIT_CODE` if the downloader
+    /// exited with one
+    #[clap(long, env = "PODCHAMP_ON_EPISODE_FAILED")]
+    pub on_episode_failed: Option<String>,
+
+    /// Command to invoke once a fetch has finished
+    ///
+    /// A JSON summary of the episodes downloaded and the episodes and feeds that failed is written
+    /// to this command's standard input
+    #[clap(long, env = "PODCHAMP_ON_FETCH_COMPLETE")]
+    pub on_fetch_complete: Option<String>,
+
+    /// Skip episodes that have already been downloaded from another feed
+    ///
+    /// Episodes are taken to be the same if they have the same GUID, the same enclosure link
+    /// (ignoring download-counting services it goes through), or the same title and duration.
+    /// `mod <feed> dedupe` overrides this for a feed.
+    #[clap(long, env = "PODCHAMP_DEDUPE")]
+    pub dedupe: bool,
+
+    /// Download episodes again when their enclosures are replaced
+    ///
+    /// An episode counts as replaced when its enclosure's link or length changes while its GUID
+    /// stays the same. The downloader gets `PODCHAMP_REPLACED=1`, so that it can overwrite the old
+    /// file.
+    #[clap(long, env = "PODCHAMP_REDOWNLOAD_REPLACED")]
+    pub redownload_replaced: bool,
+
+    /// Download at most N episodes of each feed per fetch
+    ///
+    /// The rest are left for the next fetch.
+    #[clap(long, value_name = "N", env = "PODCHAMP_MAX_DOWNLOADS")]
+    pub max_downloads: Option<u32>,
+
+    /// Log each download's output to a file in DIR, named `<feed>/<started>-<guid>.log`
+    ///
+    /// Otherwise, the downloader's output goes wherever podchamp's does, or with `--output json`,
+    /// to standard error, so as not to get mixed up with the JSON.
+    #[clap(long, value_name = "DIR", env = "PODCHAMP_DOWNLOAD_LOG_DIR")]
+    pub download_log_dir: Option<std::path::PathBuf>,
+
+    /// Stop logging a download's output once its file reaches this many bytes
+    #[clap(long, value_name = "BYTES", default_value = "1048576",
+        env = "PODCHAMP_DOWNLOAD_LOG_SIZE")]
+    pub download_log_size: u64,
+
+    /// Forget fetches and downloads older than this many days
+    #[clap(long, value_name = "DAYS", default_value = "90", env = "PODCHAMP_HISTORY_DAYS")]
+    pub history_days: u32,
+
+    /// Remember at most this many fetches, and their downloads
+    #[clap(long, value_name = "N", default_value = "1000", env = "PODCHAMP_HISTORY_RUNS")]
+    pub history_runs: u32,
+
+    /// Read at most this many pages of a feed when reading its archive, counting the feed itself
+    #[clap(long, value_name = "N", default_value = "50", env = "PODCHAMP_ARCHIVE_PAGES")]
+    pub archive_pages: usize,
+
+    /// Where to look up Apple Podcasts IDs, when adding a feed by its Apple Podcasts link
+    #[clap(long, default_value = "https://itunes.apple.com/lookup",
+        env = "PODCHAMP_APPLE_LOOKUP_URL")]
+    pub apple_lookup_url: Url,
+
+    /// The Podcast Index API, for adding feeds by their Podcast Index links or GUIDs
+    #[clap(long, default_value = "https://api.podcastindex.org/api/1.0",
+        env = "PODCHAMP_PODCAST_INDEX_URL")]
+    pub podcast_index_url: Url,
+
+    /// Podcast Index API key
+    #[clap(long, env = "PODCHAMP_PODCAST_INDEX_KEY", hide_env_values = true)]
+    pub podcast_index_key: Option<String>,
+
+    /// Podcast Index API secret
+    #[clap(long, env = "PODCHAMP_PODCAST_INDEX_SECRET", hide_env_values = true)]
+    pub podcast_index_secret: Option<String>,
+
     #[clap(subcommand)]
     pub command: Command
 }
@@ -97,21 +264,40 @@ impl Options {
         use clap::Parser;
         Self::parse()
     }
+
+    /// How the command reports what it does, for the commands that have a choice
+    pub fn output_format(&self) -> OutputFormat {
+        match self.command {
+            Command::Fetch{output, ..} | Command::Daemon{output, ..} => output,
+            _ => OutputFormat::Human,
+        }
+    }
 }
 
 #[derive(clap::Parser)]
 pub enum Command {
     /// Add a feed
     Add {
-        /// A name for the feed
+        /// A name for the feed, or if no link follows, the feed's link
+        ///
+        /// Given only a link, the feed is named after its title.
+        #[clap(value_name = "NAME")]
         name: String,
 
         /// The feed's link
-        link: Url,
+        link: Option<Url>,
 
         /// Number of most-recent episodes to fetch. Defaults to 1.
         #[clap(short = 'n', long = "backlog")]
         backlog: Option<std::num::NonZeroU32>,
+
+        /// Add the feed without fetching it first to make sure it works
+        #[clap(long)]
+        no_check: bool,
+
+        /// Add the feed even if it looks like one that's already been added
+        #[clap(short, long)]
+        force: bool,
     },
 
     /// Remove a feed
@@ -123,7 +309,95 @@ pub enum Command {
 
     /// List feeds
     #[clap(alias = "list")]
-    Ls,
+    Ls {
+        /// The columns to show, separated by commas
+        #[clap(short, long, arg_enum, use_delimiter = true, default_value = "name,state,link")]
+        columns: Vec<Column>,
+
+        /// Only list feeds with this tag; may be given more than once
+        #[clap(short, long = "tag", value_name = "TAG", number_of_values = 1)]
+        tags: Vec<String>,
+
+        /// Show every column
+        #[clap(short, long)]
+        long: bool,
+
+        /// The column to sort by
+        #[clap(short, long, arg_enum, default_value = "name")]
+        sort: Column,
+
+        /// Sort in descending order
+        #[clap(short, long)]
+        reverse: bool,
+
+        /// How to print the list
+        #[clap(long, arg_enum, default_value = "table")]
+        format: ListFormat,
+    },
+
+    /// Show how checking each feed has been going
+    Status {
+        /// Flag feeds that have failed this many times in a row
+        #[clap(long, value_name = "N", default_value = "3")]
+        failures: u32,
+
+        /// Flag feeds that haven't published an episode in this many months
+        #[clap(long, value_name = "MONTHS", default_value = "6")]
+        quiet: u32,
+    },
+
+    /// Show the history of fetches and downloads
+    Log {
+        /// Only show this feed's downloads
+        feed: Option<String>,
+
+        /// Only show what happened since DATE: a date like `2026-10-19`, an RFC 3339 time, or an
+        /// interval like `7d`, meaning that long ago
+        #[clap(long, value_name = "DATE")]
+        since: Option<Since>,
+
+        /// Only show failed downloads, or with `--runs`, fetches that didn't entirely succeed
+        #[clap(long)]
+        failed: bool,
+
+        /// List fetches rather than downloads
+        #[clap(long)]
+        runs: bool,
+
+        /// Show everything recorded about each download
+        #[clap(short, long)]
+        long: bool,
+    },
+
+    /// Search the titles and show notes of the episodes in fetched feeds
+    ///
+    /// Episodes are matched if they have every word of the query, and the best matches are listed
+    /// first.
+    Search {
+        /// The words to look for
+        #[clap(required = true)]
+        query: Vec<String>,
+
+        /// Only search this feed; may be given more than once
+        #[clap(short, long = "feed", value_name = "FEED", number_of_values = 1)]
+        feeds: Vec<String>,
+
+        /// Only search the feeds with this tag; may be given more than once
+        #[clap(short, long = "tag", value_name = "TAG", number_of_values = 1)]
+        tags: Vec<String>,
+
+        /// Show at most this many results
+        #[clap(short = 'n', long, default_value = "20")]
+        limit: u32,
+
+        /// Show the part of each episode's show notes that matched
+        #[clap(short, long)]
+        long: bool,
+
+        /// Download the best match with the feed's downloader, or with N, the Nth result
+        #[clap(long, value_name = "N", require_equals = true)]
+        download: Option<Option<std::num::NonZeroUsize>>,
+    },
 
     /// Modify a feed's settings
     #[clap(alias = "modify")]
@@ -137,8 +411,107 @@ pub enum Command {
 
     /// Fetch latest episodes
     Fetch {
-        /// A particular feed to fetch
-        feed: Option<String>,
+        /// Particular feeds to fetch
+        feeds: Vec<String>,
+
+        /// Fetch the feeds with this tag; may be given more than once
+        #[clap(short, long = "tag", value_name = "TAG", number_of_values = 1)]
+        tags: Vec<String>,
+
+        /// Check every feed, even those that aren't due to be checked yet
+        #[clap(short, long)]
+        force: bool,
+
+        /// Fetch paused feeds too
+        #[clap(long)]
+        include_paused: bool,
+
+        /// Read older pages of feeds that split their episodes across several, as far back as the
+        /// backlog goes, as if they had been set to with `mod ... archive on`
+        #[clap(long)]
+        archive: bool,
+
+        /// How to report progress
+        ///
+        /// `json` writes one JSON object per line to standard output for each event; see the README
+        /// for details
+        #[clap(long, arg_enum, default_value = "human")]
+        output: OutputFormat,
+    },
+
+    /// Stay running, checking feeds for new episodes periodically
+    Daemon {
+        /// How often to check each feed, unless set for the feed with `mod ... interval`
+        #[clap(long, default_value = "1h", env = "PODCHAMP_INTERVAL")]
+        interval: Interval,
+
+        /// How to report progress
+        #[clap(long, arg_enum, default_value = "human")]
+        output: OutputFormat,
+
+        /// Write the log to this file, rather than the terminal
+        #[clap(long, env = "PODCHAMP_LOG_FILE")]
+        log_file: Option<std::path::PathBuf>,
+
+        /// Listen on this address for WebSub hubs' callbacks, and subscribe to the hubs feeds
+        /// advertise so that their updates are fetched as soon as they're published
+        #[clap(long, requires = "websub-callback", env = "PODCHAMP_WEBSUB_LISTEN")]
+        websub_listen: Option<std::net::SocketAddr>,
+
+        /// The public URL under which hubs can reach the WebSub listener
+        #[clap(long, requires = "websub-listen", env = "PODCHAMP_WEBSUB_CALLBACK")]
+        websub_callback: Option<url::Url>,
+    },
+
+    /// Make a request of a running daemon
+    Ctl {
+        #[clap(subcommand)]
+        command: ControlCommand,
+    },
+
+    /// Stop fetching a feed for now, without forgetting it
+    Pause {
+        /// The feed to pause
+        feed: String,
+    },
+
+    /// Start fetching a paused feed again
+    Resume {
+        /// The feed to resume
+        feed: String,
+    },
+
+    /// Tag a feed, so that it can be listed and fetched along with others like it
+    Tag {
+        /// The feed to tag
+        feed: String,
+
+        /// The tags to give it
+        #[clap(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Take tags off a feed
+    Untag {
+        /// The feed to untag
+        feed: String,
+
+        /// The tags to take off
+        #[clap(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// List tags, with how many feeds have each and the settings they override
+    Tags,
+
+    /// Override settings for the feeds with a tag
+    #[clap(name = "mod-tag", alias = "modify-tag")]
+    ModTag {
+        /// The tag whose settings to change
+        tag: String,
+
+        #[clap(subcommand)]
+        how: TagModification,
     },
 
     /// Forget about episodes fetched previously
@@ -148,11 +521,47 @@ pub enum Command {
     },
 }
 
+/// Something to know about a feed, as listed by `ls`
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Name,
+    State,
+    Link,
+    Backlog,
+    FetchSince,
+    Interval,
+    NextCheck,
+    Episodes,
+    LastFetched,
+    Tags,
+    Downloader,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    Table,
+    Json,
+    Csv,
+    Tsv,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
 #[derive(clap::Parser)]
 pub enum Modification {
+    /// Rename the feed, keeping its history
+    Name {
+        /// The new name
+        name: String,
+    },
+
     /// Set the feed's link
     Link {
-        /// The new link
+        /// The new link; as with `add`, this can be a web page that links to the feed
         link: Url,
     },
 
@@ -160,5 +569,73 @@ pub enum Modification {
     Backlog {
         n: std::num::NonZeroU32,
     },
+
+    /// Set how often the daemon checks the feed, e.g. `30m` or `1d`
+    Interval {
+        /// The new interval; if omitted, the daemon's default is used
+        every: Option<Interval>,
+    },
+
+    /// Set whether to skip episodes already downloaded from other feeds
+    Dedupe {
+        /// `on` or `off`; if omitted, `--dedupe` decides
+        #[clap(arg_enum)]
+        setting: Option<Switch>,
+    },
+
+    /// Set whether to read older pages of a feed that splits its episodes across several
+    ///
+    /// Pages are followed by their `next` and `prev-archive` links until they go back as far as
+    /// the backlog.
+    Archive {
+        /// `on` or `off`
+        #[clap(arg_enum)]
+        setting: Switch,
+    },
 }
 
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+#[derive(clap::Parser)]
+pub enum TagModification {
+    /// Set the command used to download the tag's feeds' episodes
+    Downloader {
+        /// The command; if omitted, the usual downloader is used
+        command: Option<String>,
+    },
+
+    /// Set the format for `PODCHAMP_DATE` for the tag's feeds' episodes
+    DateFormat {
+        /// The format; if omitted, the usual format is used
+        format: Option<String>,
+    },
+}
+
+#[derive(clap::Parser)]
+pub enum ControlCommand {
+    /// List downloads in progress
+    Downloads,
+
+    /// Cancel a download in progress
+    Cancel {
+        /// The download's ID, as listed by `ctl downloads`
+        id: u64,
+    },
+
+    /// Restart the polling schedule, so that every feed is checked on its interval starting now
+    ///
+    /// Feed settings are read before every round of checks anyway; the daemon's own options only
+    /// change when it's restarted.
+    #[clap(alias = "reload")]
+    Reschedule,
+
+    /// Show how the last check of each feed went
+    Status {
+        /// A particular feed to show
+        feed: Option<String>,
+    },
+}
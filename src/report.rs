@@ -0,0 +1,274 @@
+
+use {
+    crate::{hooks::FetchSummary, options::OutputFormat},
+    podchamp::Episode,
+    chrono::prelude::*,
+    serde::Serialize,
+    std::{
+        io::Write as _,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// An episode, as it appears in download events
+#[derive(Debug, Serialize)]
+pub(crate) struct EpisodeInfo<'a> {
+    pub feed:  &'a str,
+    pub guid:  &'a str,
+    pub title: Option<&'a str>,
+    pub date:  DateTime<Utc>,
+    pub url:   &'a str,
+}
+
+impl<'a> EpisodeInfo<'a> {
+    pub fn new(feed: &'a str, episode: &Episode<'a>) -> Self {
+        EpisodeInfo {
+            feed,
+            guid:  episode.id,
+            title: episode.meta.title,
+            date:  episode.when,
+            url:   episode.url.as_str(),
+        }
+    }
+}
+
+/// Something that happened during a fetch
+///
+/// In JSON output, each event is written as one line, with an `event` field holding the event's
+/// name in snake case and a `time` field holding the time it was emitted.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    /// The run has started, and will fetch these feeds
+    FetchStarted { feeds: Vec<&'a str> },
+
+    /// A feed's index is being requested
+    FeedStarted { feed: &'a str, url: &'a str },
+
+    /// The server responded to a feed request
+    HttpStatus { feed: &'a str, status: u16 },
+
+    /// A feed was left alone because it isn't due to be checked yet
+    NotDue { feed: &'a str, next_check: DateTime<Utc> },
+
+    /// A feed was skipped because it's paused
+    Paused { feed: &'a str },
+
+    /// A feed's index could not be parsed
+    ParseError { feed: &'a str, error: String },
+
+    /// A feed could not be fetched for any other reason
+    FeedFailed { feed: &'a str, error: String },
+
+    /// The episodes to consider for download have been worked out
+    PlanComputed {
+        feed:        &'a str,
+        in_index:    usize,
+        in_plan:     usize,
+        fetch_since: Option<DateTime<Utc>>,
+    },
+
+    /// A feed's next check has been scheduled
+    Scheduled { feed: &'a str, next_check: DateTime<Utc> },
+
+    /// A download has been launched
+    DownloadStarted { #[serde(flatten)] episode: EpisodeInfo<'a> },
+
+    /// A download has finished successfully
+    DownloadSucceeded { #[serde(flatten)] episode: EpisodeInfo<'a> },
+
+    /// A download has failed
+    DownloadFailed {
+        #[serde(flatten)]
+        episode:  EpisodeInfo<'a>,
+        error:    String,
+        /// The last lines of the downloader's output, if it was logged to a file
+        output:   Option<String>,
+        log_file: Option<&'a str>,
+    },
+
+    /// An episode was registered without being downloaded, as another feed already had it
+    Duplicate { #[serde(flatten)] episode: EpisodeInfo<'a>, of: String },
+
+    /// An episode was taken to be one already fetched under another GUID, and registered under
+    /// its new one instead of being downloaded again
+    Remapped { #[serde(flatten)] episode: EpisodeInfo<'a>, old_guid: String },
+
+    /// An episode's enclosure has changed since it was downloaded
+    Replaced { #[serde(flatten)] episode: EpisodeInfo<'a>, old_url: Option<String> },
+
+    /// A feed has been processed
+    FeedFinished { feed: &'a str, downloaded: u32 },
+
+    /// A hook command failed
+    HookFailed { feed: Option<&'a str>, error: String },
+
+    /// A fetch was handed to the running daemon, which reports how it goes in its own log; with
+    /// neither a feed nor a tag, every feed is to be fetched
+    Queued { feed: Option<&'a str>, tag: Option<&'a str> },
+
+    /// Something worth mentioning that isn't covered by the other events
+    Note { message: String },
+
+    /// The run is over
+    Summary { #[serde(flatten)] summary: &'a FetchSummary },
+}
+
+#[derive(Serialize)]
+struct Record<'e, 'a> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'e Event<'a>,
+}
+
+/// Where reports are written
+#[derive(Debug, Clone)]
+pub(crate) enum Sink {
+    /// Standard error for human-readable text, standard output for JSON
+    Terminal,
+    /// A log file, shared between clones of the reporter
+    File(Arc<Mutex<std::fs::File>>),
+}
+
+impl Sink {
+    pub fn open_log(path: &std::path::Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Sink::File(Arc::new(Mutex::new(file))))
+    }
+}
+
+/// Reports progress, either as human-readable text or as JSON lines
+#[derive(Debug, Clone)]
+pub(crate) struct Reporter {
+    format:     OutputFormat,
+    sink:       Sink,
+    timestamps: bool,
+}
+
+impl Reporter {
+    pub fn new(format: OutputFormat) -> Self {
+        Reporter{format, sink: Sink::Terminal, timestamps: false}
+    }
+
+    /// A reporter for long-running processes, which timestamps human-readable text
+    pub fn for_log(format: OutputFormat, sink: Sink) -> Self {
+        Reporter{format, sink, timestamps: true}
+    }
+
+    pub fn emit(&self, event: Event<'_>) {
+        let now = Utc::now();
+        match self.format {
+            OutputFormat::Human => {
+                for line in Self::human_lines(&event) {
+                    if self.timestamps {
+                        self.write(false, &format!("{} {}", now.format("%F %T"), line));
+                    }
+                    else {
+                        self.write(false, &line);
+                    }
+                }
+            }
+
+            OutputFormat::Json => {
+                let record = Record{time: now, event: &event};
+                // serializing plain data to a string cannot fail
+                self.write(true, &serde_json::to_string(&record).unwrap());
+            }
+        }
+    }
+
+    fn write(&self, to_stdout: bool, line: &str) {
+        match &self.sink {
+            Sink::Terminal if to_stdout => println!("{}", line),
+            Sink::Terminal              => eprintln!("{}", line),
+            Sink::File(file) => {
+                let mut file = file.lock().unwrap();
+                if let Err(e) = writeln!(file, "{}", line) {
+                    eprintln!("Error writing log: {}", e);
+                }
+            }
+        }
+    }
+
+    fn human_lines(event: &Event<'_>) -> Vec<String> {
+        match event {
+            Event::Note{message} => vec![message.to_string()],
+
+            Event::Queued{feed, tag} => {
+                let what = match (feed, tag) {
+                    (Some(feed), _) => feed.to_string(),
+                    (_, Some(tag))  => format!("feeds tagged {}", tag),
+                    _               => "feeds".into(),
+                };
+                vec![format!("Asked the running daemon to fetch {}", what)]
+            }
+
+            Event::FetchStarted{feeds} => {
+                vec![format!("Fetching {}", feeds.join(", "))]
+            }
+
+            Event::ParseError{error, ..} | Event::FeedFailed{error, ..} => {
+                vec![format!("Fetch error: {}", error)]
+            }
+
+            Event::DownloadFailed{episode, error, output, log_file} => {
+                let mut lines = vec![format!("Download error in {}: {}", episode.feed, error)];
+                if let Some(output) = output {
+                    lines.extend(output.lines().map(|line| format!("  | {}", line)));
+                }
+                if let Some(log_file) = log_file {
+                    lines.push(format!("  Output logged to {}", log_file));
+                }
+                lines
+            }
+
+            Event::Duplicate{episode, of} => {
+                let title = episode.title.unwrap_or("(untitled)");
+                vec![format!("Skipped {} in {}, as it was already downloaded from {}",
+                    title, episode.feed, of)]
+            }
+
+            Event::Replaced{episode, ..} => {
+                let title = episode.title.unwrap_or("(untitled)");
+                vec![format!("The enclosure of {} in {} has been replaced", title, episode.feed)]
+            }
+
+            Event::HookFailed{feed: Some(feed), error} => {
+                vec![format!("Hook error in {}: {}", feed, error)]
+            }
+
+            Event::HookFailed{feed: None, error} => {
+                vec![format!("Hook error: {}", error)]
+            }
+
+            Event::Summary{summary} => {
+                let mut lines = Vec::new();
+                let any_failed = !summary.failed_feeds.is_empty() || !summary.failed.is_empty();
+                if summary.downloaded.is_empty() && !any_failed {
+                    lines.push("Already up-to-date".into());
+                }
+
+                if any_failed {
+                    lines.push("Errors:".into());
+                }
+
+                for failure in &summary.failed_feeds {
+                    lines.push(format!("  {}: {}", failure.feed, failure.error));
+                }
+
+                for episode in &summary.failed {
+                    let title = episode.title.as_deref().unwrap_or("(untitled)");
+                    let error = episode.error.as_deref().unwrap_or("unknown error");
+                    lines.push(format!("  {}: {}: {}", episode.feed, title, error));
+                }
+
+                lines
+            }
+
+            _ => Vec::new(),
+        }
+    }
+}
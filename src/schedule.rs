@@ -0,0 +1,306 @@
+
+use {
+    crate::FeedIndex,
+    chrono::{prelude::*, Duration},
+};
+
+/// Never check a feed more often than this many minutes, whatever it says
+pub const MIN_WAIT_MINUTES: i64 = 15;
+
+/// Always check a feed at least this many days, whatever it says
+pub const MAX_WAIT_DAYS: i64 = 7;
+
+/// How many of the most recent episodes to consider when working out a feed's publishing cadence
+const CADENCE_SAMPLE: usize = 10;
+
+/// What a feed and the server it came from say about how often it's worth checking
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedHints {
+    /// RSS `<ttl>`
+    pub ttl:           Option<Duration>,
+    /// RSS syndication module `<sy:updatePeriod>` divided by `<sy:updateFrequency>`
+    pub update_period: Option<Duration>,
+    /// RSS `<skipHours>`, as UTC hours of the day
+    pub skip_hours:    Vec<u32>,
+    /// RSS `<skipDays>`
+    pub skip_days:     Vec<Weekday>,
+    /// HTTP `Cache-Control: max-age`
+    pub max_age:       Option<Duration>,
+    /// HTTP `Expires`
+    pub expires:       Option<DateTime<Utc>>,
+}
+
+impl FeedHints {
+    /// Picks hints out of a feed document
+    ///
+    /// Anything feed-rs doesn't expose is scanned for directly; malformed documents just give
+    /// fewer hints.
+    pub fn from_feed(raw: &feed_rs::model::Feed, xml: &[u8]) -> Self {
+        let mut hints = FeedHints {
+            ttl: raw.ttl.map(|mins| Duration::minutes(mins as i64)),
+            .. Default::default()
+        };
+
+        use quick_xml::events::Event;
+        let mut reader = quick_xml::Reader::from_reader(xml);
+        reader.trim_text(true);
+
+        let mut buf = Vec::new();
+        let mut path: Vec<Vec<u8>> = Vec::new();
+        let mut period = None;
+        let mut frequency = None;
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::Start(e)) => path.push(e.local_name().to_ascii_lowercase()),
+                Ok(Event::End(_))   => { path.pop(); }
+                Ok(Event::Text(text)) => {
+                    let text = text.unescape_and_decode(&reader).unwrap_or_default();
+                    let parent = path.len().checked_sub(2).map(|i| &path[i][..]);
+                    match (parent, path.last().map(|name| &name[..])) {
+                        (_, Some(b"updateperiod")) => period = parse_update_period(&text),
+                        (_, Some(b"updatefrequency")) => frequency = text.parse::<i32>().ok(),
+                        (Some(b"skiphours"), Some(b"hour")) => {
+                            if let Ok(hour) = text.parse::<u32>() {
+                                hints.skip_hours.push(hour % 24);
+                            }
+                        }
+                        (Some(b"skipdays"), Some(b"day")) => {
+                            if let Ok(day) = text.parse::<Weekday>() {
+                                hints.skip_days.push(day);
+                            }
+                        }
+                        _ => { }
+                    }
+                }
+                Ok(Event::Eof) | Err(_) => break,
+                _ => { }
+            }
+            buf.clear();
+        }
+
+        hints.update_period = period.map(|period| period / frequency.unwrap_or(1).max(1));
+        hints
+    }
+
+    /// Adds the hints given by an HTTP response's caching headers
+    pub fn with_http_headers(mut self, cache_control: Option<&str>, expires: Option<&str>) -> Self {
+        self.max_age = cache_control.and_then(parse_max_age);
+        self.expires = expires
+            .and_then(|expires| DateTime::parse_from_rfc2822(expires).ok())
+            .map(|expires| expires.with_timezone(&Utc));
+        self
+    }
+}
+
+fn parse_update_period(period: &str) -> Option<Duration> {
+    match period.trim() {
+        "hourly"  => Some(Duration::hours(1)),
+        "daily"   => Some(Duration::days(1)),
+        "weekly"  => Some(Duration::weeks(1)),
+        "monthly" => Some(Duration::days(30)),
+        "yearly"  => Some(Duration::days(365)),
+        _         => None,
+    }
+}
+
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    cache_control.split(',')
+        .map(str::trim)
+        .find_map(|directive| directive.strip_prefix("max-age="))
+        .and_then(|secs| secs.trim_matches('"').parse::<i64>().ok())
+        .map(Duration::seconds)
+}
+
+/// The typical time between episodes, going by the most recent ones
+pub fn publishing_cadence(index: &FeedIndex<'_>) -> Option<Duration> {
+    let recent = &index[..index.len().min(CADENCE_SAMPLE)];
+    let mut gaps: Vec<Duration> = recent.windows(2)
+        .map(|pair| pair[0].when - pair[1].when)
+        .collect();
+    if gaps.is_empty() {
+        return None;
+    }
+
+    gaps.sort_unstable();
+    Some(gaps[gaps.len() / 2])
+}
+
+/// Works out when a feed should next be checked, having just been checked at `now`
+///
+/// Checks are spaced out to a quarter of the feed's publishing cadence, but no closer than the feed
+/// or server asks, and within [`MIN_WAIT_MINUTES`] and [`MAX_WAIT_DAYS`]. The result is then
+/// pushed past any hours or days the feed asks to be skipped.
+pub fn next_check(now: DateTime<Utc>, hints: &FeedHints, index: &FeedIndex<'_>)
+    -> DateTime<Utc>
+{
+    let requested = [
+        hints.ttl,
+        hints.update_period,
+        hints.max_age,
+        hints.expires.map(|expires| expires - now),
+    ];
+
+    let wait = requested.iter()
+        .flatten()
+        .copied()
+        .chain(publishing_cadence(index).map(|cadence| cadence / 4))
+        .max()
+        .unwrap_or_else(|| Duration::minutes(MIN_WAIT_MINUTES))
+        .max(Duration::minutes(MIN_WAIT_MINUTES))
+        .min(Duration::days(MAX_WAIT_DAYS));
+
+    let mut next = now + wait;
+    for _ in 0 .. 7 * 24 {
+        let skip = hints.skip_hours.contains(&next.hour())
+                || hints.skip_days.contains(&next.weekday());
+        if !skip { break; }
+        next = (next + Duration::hours(1))
+            .with_minute(0).and_then(|t| t.with_second(0))
+            .unwrap_or(next + Duration::hours(1));
+    }
+
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::{Episode, EpisodeMeta}, url::Url};
+
+    /// Monday 19 October 2026, 10:00 UTC
+    fn monday() -> DateTime<Utc> {
+        Utc.ymd(2026, 10, 19).and_hms(10, 0, 0)
+    }
+
+    fn index<'a>(link: &'a Url, dates: &[DateTime<Utc>]) -> FeedIndex<'a> {
+        dates.iter()
+            .map(|&when| Episode {
+                meta: EpisodeMeta{title: None, duration: None, length: None, description: None},
+                id:   "ep",
+                url:  link,
+                when,
+            })
+            .collect()
+    }
+
+    fn weekly<'a>(link: &'a Url, n: i64) -> FeedIndex<'a> {
+        let dates: Vec<_> = (1 ..= n).map(|i| monday() - Duration::weeks(i)).collect();
+        index(link, &dates)
+    }
+
+    fn link() -> Url {
+        Url::parse("https://example.com/ep.mp3").unwrap()
+    }
+
+    #[test]
+    fn max_age() {
+        assert_eq!(parse_max_age("public, max-age=3600"), Some(Duration::hours(1)));
+        assert_eq!(parse_max_age("max-age=\"60\", must-revalidate"), Some(Duration::minutes(1)));
+        assert_eq!(parse_max_age("no-cache"), None);
+        assert_eq!(parse_max_age("max-age=soon"), None);
+    }
+
+    #[test]
+    fn expires() {
+        let hints = FeedHints::default()
+            .with_http_headers(None, Some("Mon, 19 Oct 2026 12:00:00 GMT"));
+        assert_eq!(hints.expires, Some(monday() + Duration::hours(2)));
+
+        let hints = FeedHints::default().with_http_headers(None, Some("0"));
+        assert_eq!(hints.expires, None);
+    }
+
+    #[test]
+    fn cadence_is_the_median_gap() {
+        let link = link();
+        assert_eq!(publishing_cadence(&weekly(&link, 5)), Some(Duration::weeks(1)));
+
+        // one bonus episode doesn't make it a twice-weekly show
+        let mut dates: Vec<_> = (1 ..= 5).map(|i| monday() - Duration::weeks(i)).collect();
+        dates.push(monday() - Duration::weeks(2) + Duration::days(1));
+        assert_eq!(publishing_cadence(&index(&link, &dates)), Some(Duration::weeks(1)));
+
+        assert_eq!(publishing_cadence(&weekly(&link, 1)), None);
+    }
+
+    #[test]
+    fn waits_a_quarter_of_the_cadence() {
+        let link = link();
+        let next = next_check(monday(), &FeedHints::default(), &weekly(&link, 5));
+        assert_eq!(next, monday() + Duration::hours(42));
+    }
+
+    #[test]
+    fn wait_is_clamped() {
+        let link = link();
+        let empty = index(&link, &[]);
+
+        // nothing to go on, or a hint that's too short
+        assert_eq!(next_check(monday(), &FeedHints::default(), &empty),
+            monday() + Duration::minutes(MIN_WAIT_MINUTES));
+        let hints = FeedHints{ttl: Some(Duration::minutes(1)), ..Default::default()};
+        assert_eq!(next_check(monday(), &hints, &empty),
+            monday() + Duration::minutes(MIN_WAIT_MINUTES));
+
+        // an expiry time in the past
+        let hints = FeedHints{expires: Some(monday() - Duration::days(1)), ..Default::default()};
+        assert_eq!(next_check(monday(), &hints, &empty),
+            monday() + Duration::minutes(MIN_WAIT_MINUTES));
+
+        // a hint that's too long
+        let hints = FeedHints{ttl: Some(Duration::days(30)), ..Default::default()};
+        assert_eq!(next_check(monday(), &hints, &empty), monday() + Duration::days(MAX_WAIT_DAYS));
+    }
+
+    #[test]
+    fn longest_hint_wins() {
+        let link = link();
+        let hints = FeedHints {
+            ttl:     Some(Duration::hours(1)),
+            max_age: Some(Duration::hours(3)),
+            ..Default::default()
+        };
+        assert_eq!(next_check(monday(), &hints, &index(&link, &[])), monday() + Duration::hours(3));
+    }
+
+    #[test]
+    fn skips_hours_and_days() {
+        let link = link();
+        let empty = index(&link, &[]);
+
+        let hints = FeedHints{skip_hours: vec![10, 11], ..Default::default()};
+        assert_eq!(next_check(monday(), &hints, &empty), Utc.ymd(2026, 10, 19).and_hms(12, 0, 0));
+
+        let hints = FeedHints{skip_days: vec![Weekday::Mon], ..Default::default()};
+        assert_eq!(next_check(monday(), &hints, &empty), Utc.ymd(2026, 10, 20).and_hms(0, 0, 0));
+
+        // a feed that asks never to be checked is checked anyway
+        let hints = FeedHints{skip_hours: (0 .. 24).collect(), ..Default::default()};
+        assert!(next_check(monday(), &hints, &empty) > monday());
+    }
+
+    #[test]
+    fn hints_from_feed() {
+        let xml = br#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+            <channel>
+                <title>Show</title>
+                <ttl>90</ttl>
+                <sy:updatePeriod>daily</sy:updatePeriod>
+                <sy:updateFrequency>2</sy:updateFrequency>
+                <skipHours><hour>0</hour><hour>25</hour></skipHours>
+                <skipDays><day>Saturday</day><day>Sunday</day></skipDays>
+                <item><title>Not an hour</title><hour>5</hour></item>
+            </channel>
+            </rss>"#;
+        let raw = feed_rs::parser::parse(&xml[..]).unwrap();
+        let hints = FeedHints::from_feed(&raw, xml);
+        assert_eq!(hints.ttl, Some(Duration::minutes(90)));
+        assert_eq!(hints.update_period, Some(Duration::hours(12)));
+        assert_eq!(hints.skip_hours, [0, 1]);
+        assert_eq!(hints.skip_days, [Weekday::Sat, Weekday::Sun]);
+
+        let xml = br#"<rss version="2.0"><channel><title>Bare</title></channel></rss>"#;
+        let raw = feed_rs::parser::parse(&xml[..]).unwrap();
+        assert_eq!(FeedHints::from_feed(&raw, xml), FeedHints::default());
+    }
+}
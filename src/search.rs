@@ -0,0 +1,99 @@
+/// Turns HTML, like an episode's show notes, into plain text, with the tags taken out, character
+/// references decoded and runs of whitespace squashed into single spaces
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[.. start]);
+        // a tag may separate words, as `<br>` or `</p>` do
+        text.push(' ');
+        rest = match rest[start ..].find('>') {
+            Some(end) => &rest[start + end + 1 ..],
+            None      => "",
+        };
+    }
+    text.push_str(rest);
+
+    decode_references(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[.. start]);
+        rest = &rest[start ..];
+
+        let reference = rest[1 ..].find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_reference(&rest[1 .. end + 1])?, end + 2)));
+        match reference {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len ..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1 ..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decodes the name of a character reference, without its `&` and `;`
+fn decode_reference(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None      => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    Some(match name {
+        "amp"    => '&',
+        "lt"     => '<',
+        "gt"     => '>',
+        "quot"   => '"',
+        "apos"   => '\'',
+        "nbsp"   => ' ',
+        "lsquo"  => '‘',
+        "rsquo"  => '’',
+        "ldquo"  => '“',
+        "rdquo"  => '”',
+        "ndash"  => '–',
+        "mdash"  => '—',
+        "hellip" => '…',
+        _        => return None,
+    })
+}
+
+/// Makes an FTS5 query for entries with every word of `query` in them
+///
+/// Each word is quoted, so that punctuation, like the hyphen in `call-in`, is taken as part of
+/// the text to look for rather than as query syntax. A query with no words gives nothing, as FTS5
+/// can't match an empty expression.
+pub fn match_expression(query: &str) -> Option<String> {
+    let words: Vec<String> = query.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    match words.is_empty() {
+        true  => None,
+        false => Some(words.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_expressions() {
+        assert_eq!(match_expression("call-in  show").as_deref(), Some(r#""call-in" "show""#));
+        assert_eq!(match_expression(r#"say "hi""#).as_deref(), Some(r#""say" """hi""""#));
+        assert_eq!(match_expression(""), None);
+        assert_eq!(match_expression(" \t\n"), None);
+    }
+}
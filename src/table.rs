@@ -0,0 +1,41 @@
+
+/// Rows of text, printed with each column as wide as its widest cell
+pub(crate) struct Table {
+    headers: Vec<String>,
+    rows:    Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<S: Into<String>>(headers: impl IntoIterator<Item = S>) -> Self {
+        Table {
+            headers: headers.into_iter().map(Into::into).collect(),
+            rows:    Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self) {
+        let width = |cell: &str| cell.chars().count();
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| width(header)).collect();
+        for row in &self.rows {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(width(cell));
+            }
+        }
+
+        let print_row = |cells: &mut dyn Iterator<Item = &str>| {
+            let line: Vec<String> = cells.zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            println!("{}", line.join("  ").trim_end());
+        };
+
+        print_row(&mut self.headers.iter().map(String::as_str));
+        for row in &self.rows {
+            print_row(&mut row.iter().map(String::as_str));
+        }
+    }
+}
@@ -0,0 +1,222 @@
+
+use url::Url;
+
+/// Where to subscribe for push notifications of a feed's updates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discovery {
+    /// The hub to subscribe with
+    pub hub:   Url,
+    /// The feed's canonical URL, which identifies it to the hub
+    pub topic: Url,
+}
+
+/// Looks for a WebSub hub advertised by a feed, either in an HTTP `Link` header or in `<link>` (or
+/// `<atom:link>`) elements at the top level of the feed document
+///
+/// If the feed doesn't name its own canonical URL, `fetched_from` is used as the topic.
+pub fn discover(xml: &[u8], link_header: Option<&str>, fetched_from: &Url) -> Option<Discovery> {
+    let mut hub = None;
+    let mut topic = None;
+
+    if let Some(header) = link_header {
+        for (target, rel) in parse_link_header(header) {
+            let rels = || rel.split_ascii_whitespace();
+            if hub.is_none() && rels().any(|rel| rel == "hub") {
+                hub = Url::parse(&target).ok();
+            }
+            if topic.is_none() && rels().any(|rel| rel == "self") {
+                topic = Url::parse(&target).ok();
+            }
+        }
+    }
+
+    if hub.is_none() || topic.is_none() {
+        let (doc_hub, doc_topic) = scan_document(xml);
+        hub = hub.or(doc_hub);
+        topic = topic.or(doc_topic);
+    }
+
+    Some(Discovery {
+        hub:   hub?,
+        topic: topic.unwrap_or_else(|| fetched_from.clone()),
+    })
+}
+
+/// Splits an HTTP `Link` header into (target, rel) pairs
+pub(crate) fn parse_link_header(header: &str) -> Vec<(String, String)> {
+    header.split(',')
+        .filter_map(|link| {
+            let mut parts = link.split(';');
+            let target = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+            let rel = parts
+                .filter_map(|param| param.trim().strip_prefix("rel="))
+                .map(|rel| rel.trim_matches('"').to_owned())
+                .next()?;
+            Some((target.to_owned(), rel))
+        })
+        .collect()
+}
+
+/// Finds the hub and self links outside of any entries
+fn scan_document(xml: &[u8]) -> (Option<Url>, Option<Url>) {
+    use quick_xml::events::Event;
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut in_entry = 0;
+    let mut hub = None;
+    let mut topic = None;
+
+    loop {
+        let event = match reader.read_event(&mut buf) {
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(event) => event,
+        };
+
+        match &event {
+            Event::Start(e) if matches!(e.local_name(), b"item" | b"entry") => in_entry += 1,
+            Event::End(e)   if matches!(e.local_name(), b"item" | b"entry") => in_entry -= 1,
+            _ => { }
+        }
+
+        if let Event::Start(e) | Event::Empty(e) = &event {
+            if in_entry == 0 && e.local_name() == b"link" {
+                let mut rel = None;
+                let mut href = None;
+                for attr in e.attributes().flatten() {
+                    let value = attr.unescape_and_decode_value(&reader).ok();
+                    match attr.key {
+                        b"rel"  => rel = value,
+                        b"href" => href = value,
+                        _ => { }
+                    }
+                }
+
+                let href = href.and_then(|href| Url::parse(&href).ok());
+                match rel.as_deref() {
+                    Some("hub")  if hub.is_none()   => hub = href,
+                    Some("self") if topic.is_none() => topic = href,
+                    _ => { }
+                }
+            }
+        }
+
+        buf.clear();
+    }
+
+    (hub, topic)
+}
+
+/// Checks an `X-Hub-Signature` header against a pushed notification's body
+///
+/// Hubs may sign with SHA-1, SHA-256 or SHA-512; anything else is rejected.
+pub fn signature_valid(secret: &[u8], header: &str, body: &[u8]) -> bool {
+    use hmac::{Hmac, Mac};
+
+    let (method, signature) = match header.split_once('=') {
+        Some(split) => split,
+        None        => return false,
+    };
+
+    let signature = match decode_hex(signature.trim()) {
+        Some(signature) => signature,
+        None            => return false,
+    };
+
+    macro_rules! verify {
+        ($digest:ty) => {
+            match Hmac::<$digest>::new_from_slice(secret) {
+                Ok(mut mac) => { mac.update(body); mac.verify_slice(&signature).is_ok() }
+                Err(_)      => false,
+            }
+        }
+    }
+
+    match method.trim() {
+        "sha1"   => verify!(sha1::Sha1),
+        "sha256" => verify!(sha2::Sha256),
+        "sha512" => verify!(sha2::Sha512),
+        _        => false,
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0 .. hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i .. i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encodes bytes as lowercase hex
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+    #[test]
+    fn signatures() {
+        let sha1 = "sha1=de7c9b85b8b78aa6bc8a7a36f70a90701c9db4d9";
+        let sha256 = "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8";
+        assert!(signature_valid(b"key", sha1, FOX));
+        assert!(signature_valid(b"key", sha256, FOX));
+
+        assert!(!signature_valid(b"other key", sha256, FOX));
+        assert!(!signature_valid(b"key", sha256, b"The quick brown fox"));
+        assert!(!signature_valid(b"key", &sha256.replace("sha256", "md5"), FOX));
+        assert!(!signature_valid(b"key", &sha256[.. sha256.len() - 1], FOX));
+        assert!(!signature_valid(b"key", "f7bc83f4", FOX));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(decode_hex("00ff7A"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("é1"), None);
+        assert_eq!(encode_hex(&[0x00, 0xff, 0x7a]), "00ff7a");
+    }
+
+    #[test]
+    fn link_header() {
+        let header = r#"<https://hub.example/>; rel="hub", <https://example.com/feed>; rel=self,
+            <https://example.com/other>; title="no rel", junk"#;
+        assert_eq!(parse_link_header(header), [
+            ("https://hub.example/".to_owned(), "hub".to_owned()),
+            ("https://example.com/feed".to_owned(), "self".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn discovery() {
+        let fetched_from = Url::parse("http://example.com/feed").unwrap();
+        let xml = br#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>
+            <item><atom:link rel="hub" href="https://wrong.example/"/></item>
+            <atom:link rel="hub" href="https://hub.example/"/>
+            <atom:link rel="self" href="https://example.com/feed.xml"/>
+        </channel></rss>"#;
+
+        let found = discover(xml, None, &fetched_from).unwrap();
+        assert_eq!(found.hub.as_str(), "https://hub.example/");
+        assert_eq!(found.topic.as_str(), "https://example.com/feed.xml");
+
+        // the header takes precedence over the document
+        let header = r#"<https://other-hub.example/>; rel="hub""#;
+        let found = discover(xml, Some(header), &fetched_from).unwrap();
+        assert_eq!(found.hub.as_str(), "https://other-hub.example/");
+        assert_eq!(found.topic.as_str(), "https://example.com/feed.xml");
+
+        // without a self link, the topic is where the feed came from
+        let found = discover(b"<rss/>", Some(header), &fetched_from).unwrap();
+        assert_eq!(found.topic, fetched_from);
+
+        assert!(discover(b"<rss/>", None, &fetched_from).is_none());
+    }
+}
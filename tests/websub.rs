@@ -0,0 +1,303 @@
+//! Runs a daemon with its WebSub listener against a stand-in hub, which also serves the feed
+
+use {
+    hmac::{Hmac, Mac},
+    hyper::{Body, Method, Request, Response, StatusCode},
+    podchamp::websub::encode_hex,
+    std::{
+        collections::HashMap,
+        convert::Infallible,
+        net::{SocketAddr, TcpListener},
+        sync::{Arc, atomic::{AtomicUsize, Ordering}},
+        time::Duration,
+    },
+    tokio::sync::mpsc,
+};
+
+const FEED: &str = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+    <title>Pushy</title>
+    <atom:link rel="hub" href="http://{addr}/hub"/>
+    <atom:link rel="self" href="http://{addr}/feed.xml"/>
+    <item>
+        <title>Episode 1</title>
+        <guid>pushy-1</guid>
+        <pubDate>Mon, 12 Oct 2026 10:00:00 GMT</pubDate>
+        <enclosure url="http://{addr}/1.mp3" type="audio/mpeg" length="1"/>
+    </item>
+</channel>
+</rss>
+"#;
+
+/// How long the stand-in takes to answer for `/slow.xml`, to hold up a daemon's round of checks
+const SLOW: Duration = Duration::from_secs(5);
+
+/// What the stand-in has seen
+struct Hub {
+    addr:          SocketAddr,
+    fetches:       Arc<AtomicUsize>,
+    slow_fetches:  Arc<AtomicUsize>,
+    subscriptions: mpsc::UnboundedReceiver<HashMap<String, String>>,
+}
+
+impl Hub {
+    fn start() -> Hub {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let slow_fetches = Arc::new(AtomicUsize::new(0));
+        let (subscribe, subscriptions) = mpsc::unbounded_channel();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let feed = FEED.replace("{addr}", &addr.to_string());
+
+        let make_service = hyper::service::make_service_fn({
+            let (fetches, slow_fetches) = (fetches.clone(), slow_fetches.clone());
+            move |_| {
+                let (fetches, slow_fetches) = (fetches.clone(), slow_fetches.clone());
+                let (subscribe, feed) = (subscribe.clone(), feed.clone());
+                async move {
+                    Ok::<_, Infallible>(hyper::service::service_fn(move |req: Request<Body>| {
+                        let (fetches, slow_fetches) = (fetches.clone(), slow_fetches.clone());
+                        let (subscribe, feed) = (subscribe.clone(), feed.clone());
+                        async move {
+                            let response = match (req.method(), req.uri().path()) {
+                                (&Method::GET, "/feed.xml") => {
+                                    fetches.fetch_add(1, Ordering::SeqCst);
+                                    Response::new(Body::from(feed))
+                                }
+
+                                (&Method::GET, "/slow.xml") => {
+                                    slow_fetches.fetch_add(1, Ordering::SeqCst);
+                                    tokio::time::sleep(SLOW).await;
+                                    let mut response = Response::new(Body::empty());
+                                    *response.status_mut() = StatusCode::NOT_FOUND;
+                                    response
+                                }
+
+                                (&Method::POST, "/hub") => {
+                                    let body = hyper::body::to_bytes(req.into_body()).await
+                                        .unwrap();
+                                    let form = url::form_urlencoded::parse(&body)
+                                        .into_owned()
+                                        .collect();
+                                    let _ = subscribe.send(form);
+                                    let mut response = Response::new(Body::empty());
+                                    *response.status_mut() = StatusCode::ACCEPTED;
+                                    response
+                                }
+
+                                _ => {
+                                    let mut response = Response::new(Body::empty());
+                                    *response.status_mut() = StatusCode::NOT_FOUND;
+                                    response
+                                }
+                            };
+                            Ok::<_, Infallible>(response)
+                        }
+                    }))
+                }
+            }
+        });
+
+        let server = hyper::Server::from_tcp(listener).unwrap().serve(make_service);
+        tokio::spawn(server);
+        Hub{addr, fetches, slow_fetches, subscriptions}
+    }
+
+    fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the feed to have been fetched `n` times
+    async fn fetched(&self, n: usize) -> bool {
+        counted(&self.fetches, n).await
+    }
+}
+
+/// Waits for `count` to reach `n`
+async fn counted(count: &AtomicUsize, n: usize) -> bool {
+    for _ in 0 .. 100 {
+        if count.load(Ordering::SeqCst) >= n {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+fn free_port() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+fn podchamp(dir: &std::path::Path) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_podchamp"));
+    command
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("XDG_RUNTIME_DIR", dir.join("run"))
+        // nothing's really downloaded
+        .env("PODCHAMP_DOWNLOADER", "true")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true);
+    command
+}
+
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("podchamp-websub-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("run")).unwrap();
+    dir
+}
+
+fn daemon(dir: &std::path::Path, listen: SocketAddr, callback_base: &str)
+    -> tokio::process::Child
+{
+    podchamp(dir)
+        .args(["daemon", "--websub-listen", &listen.to_string()])
+        .args(["--websub-callback", callback_base])
+        .spawn()
+        .unwrap()
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", encode_hex(&mac.finalize().into_bytes()))
+}
+
+#[tokio::test]
+async fn subscribes_verifies_and_fetches_on_push() {
+    let dir = test_dir("push");
+
+    let mut hub = Hub::start();
+    let feed_url = format!("http://{}/feed.xml", hub.addr);
+
+    let added = podchamp(&dir).args(["add", "--no-check", "pushy", &feed_url]).status().await
+        .unwrap();
+    assert!(added.success());
+
+    let listen = free_port();
+    let callback_base = format!("http://{}/websub", listen);
+    let _daemon = daemon(&dir, listen, &callback_base);
+
+    // the daemon fetches the feed, finds the hub, and subscribes
+    let subscription = tokio::time::timeout(Duration::from_secs(10), hub.subscriptions.recv())
+        .await
+        .expect("no subscription request")
+        .unwrap();
+    assert_eq!(hub.fetches(), 1);
+    assert_eq!(subscription["hub.mode"], "subscribe");
+    assert_eq!(subscription["hub.topic"], feed_url);
+    let callback = &subscription["hub.callback"];
+    let secret = &subscription["hub.secret"];
+    assert!(callback.starts_with(&callback_base));
+
+    // the verification of intent
+    let client = reqwest::Client::new();
+    let verify = |topic: &str| client.get(callback)
+        .query(&[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", topic),
+            ("hub.challenge", "prove-it"),
+            ("hub.lease_seconds", "600"),
+        ])
+        .send();
+
+    let response = verify("http://elsewhere.example/feed.xml").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = verify(&feed_url).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "prove-it");
+
+    let response = client.get(format!("{}/unknown", callback_base)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    // a badly-signed push is acknowledged but ignored
+    let body = FEED.as_bytes();
+    let response = client.post(callback)
+        .header("x-hub-signature", sign("not the secret", body))
+        .body(body)
+        .send().await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(hub.fetches(), 1);
+
+    // a properly signed one gets the feed fetched
+    let response = client.post(callback)
+        .header("x-hub-signature", sign(secret, body))
+        .body(body)
+        .send().await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(hub.fetched(2).await, "pushed feed wasn't fetched");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn answers_for_subscriptions_after_a_restart() {
+    let dir = test_dir("restart");
+    let mut hub = Hub::start();
+    let feed_url = format!("http://{}/feed.xml", hub.addr);
+
+    let added = podchamp(&dir).args(["add", "--no-check", "pushy", &feed_url]).status().await
+        .unwrap();
+    assert!(added.success());
+
+    let listen = free_port();
+    let callback_base = format!("http://{}/websub", listen);
+    let mut first = daemon(&dir, listen, &callback_base);
+
+    let subscription = tokio::time::timeout(Duration::from_secs(10), hub.subscriptions.recv())
+        .await
+        .expect("no subscription request")
+        .unwrap();
+    let callback = &subscription["hub.callback"];
+    let secret = &subscription["hub.secret"];
+
+    let client = reqwest::Client::new();
+    let verify = || client.get(callback)
+        .query(&[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &feed_url[..]),
+            ("hub.challenge", "prove-it"),
+            ("hub.lease_seconds", "600"),
+        ])
+        .send();
+    assert_eq!(verify().await.unwrap().status(), StatusCode::OK);
+
+    // give the daemon a moment to record the lease, then stop it
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    first.kill().await.unwrap();
+
+    // a feed that's slow to answer holds up the next daemon's first round of checks
+    let slow_url = format!("http://{}/slow.xml", hub.addr);
+    let added = podchamp(&dir).args(["add", "--no-check", "slow", &slow_url]).status().await
+        .unwrap();
+    assert!(added.success());
+
+    let _second = daemon(&dir, listen, &callback_base);
+    assert!(counted(&hub.slow_fetches, 1).await, "slow feed wasn't fetched");
+
+    // the hub's calls are still answered, rather than told the subscription is gone
+    let response = verify().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "prove-it");
+
+    let fetches = hub.fetches();
+    let body = FEED.as_bytes();
+    let response = client.post(callback)
+        .header("x-hub-signature", sign(secret, body))
+        .body(body)
+        .send().await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(hub.fetched(fetches + 1).await, "pushed feed wasn't fetched");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}